@@ -1,14 +1,20 @@
 //! Local configuration
 
 use std::{
-    fs, io,
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt, fs, io, mem,
+    num::NonZeroUsize,
     ops::Deref,
     path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
 };
 
 use anyhow::Context as _;
 use serde::de::Deserialize as _;
 use simple_expand_tilde::expand_tilde;
+use unicode_normalization::UnicodeNormalization as _;
 
 /// Local configuration
 #[derive(Debug, serde::Deserialize)]
@@ -17,6 +23,8 @@ pub(crate) struct Config {
     pub url: url::Url,
     /// Syncthing API key
     pub api_key: String,
+    /// Maximum number of hook processes running at the same time, unlimited if not set
+    pub max_concurrent_hooks: Option<NonZeroUsize>,
 }
 
 /// Root local Syncthing configuration
@@ -58,6 +66,7 @@ impl Config {
         Ok(Self {
             url: url::Url::parse(&format!("http://{}", st_config.gui.address))?,
             api_key: st_config.gui.apikey,
+            max_concurrent_hooks: None,
         })
     }
 }
@@ -66,6 +75,7 @@ impl Config {
 #[derive(Debug, serde::Deserialize)]
 pub(crate) struct FolderConfig {
     /// Hooks array
+    #[serde(deserialize_with = "deserialize_hooks")]
     pub hooks: Vec<FolderHook>,
 }
 
@@ -94,6 +104,12 @@ impl TryFrom<&Path> for NormalizedPath {
     }
 }
 
+impl AsRef<Path> for NormalizedPath {
+    fn as_ref(&self) -> &Path {
+        self.0.as_path()
+    }
+}
+
 impl Deref for NormalizedPath {
     type Target = Path;
 
@@ -105,54 +121,763 @@ impl Deref for NormalizedPath {
 /// Configuration for a folder hook
 #[derive(Debug, serde::Deserialize)]
 pub(crate) struct FolderHook {
-    /// Absolute path of the folder
-    pub folder: NormalizedPath,
+    /// Name of the hook, for its environment
+    pub name: Option<String>,
+    /// Folder the hook applies to
+    #[serde(flatten)]
+    pub folder: FolderSelector,
     /// Event to hook
     pub event: FolderEvent,
-    /// Event filter
+    /// Event filter, matching if any of its globs matches
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_globs")]
+    pub filter: Option<GlobList>,
+    /// Event exclusion filter, taking precedence over `filter`
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_globs")]
+    pub exclude: Option<GlobList>,
+    /// Event regex filter, matching the whole path
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub filter_regex: Option<regex::Regex>,
+    /// Normalize path filters and event paths to Unicode NFC before matching, so that composed
+    /// and decomposed forms of the same characters match
+    pub normalize_unicode: Option<bool>,
+    /// Match path filters ignoring case
+    pub case_insensitive: Option<bool>,
+    /// Minimum size of the event file
+    pub min_size: Option<bytesize::ByteSize>,
+    /// Maximum size of the event file
+    pub max_size: Option<bytesize::ByteSize>,
+    /// Maximum time elapsed since the last modification of the event file
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub max_age: Option<Duration>,
+    /// MIME types the content of the event file can have, possibly with a `type/*` wildcard
     #[serde(default)]
-    #[serde(deserialize_with = "deserialize_glob")]
-    pub filter: Option<globset::GlobMatcher>,
+    #[serde(deserialize_with = "deserialize_one_or_many")]
+    pub mime: Vec<String>,
+    /// Ids or names of the devices the event file must have been last modified by
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_one_or_many")]
+    pub modified_by: Vec<String>,
+    /// Condition expression on the event, type checked when parsed
+    pub when: Option<crate::expr::Expr>,
+    /// Command to run before the hook command, which only runs if it succeeds
+    pub condition: Option<CommandLine>,
+    /// Maximum duration of the condition command, after which it is killed and considered failed
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub condition_timeout: Option<Duration>,
+    /// Time windows the hook can run in, runs outside of them are deferred
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_one_or_many")]
+    pub schedule: Vec<crate::schedule::Window>,
+    /// Quiet time to collect matching events for, before running the hook once for all of them
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub debounce: Option<Duration>,
+    /// Separator of the paths in the file of the events collected when debouncing
+    pub paths_separator: Option<PathsSeparator>,
     /// Command
-    #[serde(deserialize_with = "deserialize_command")]
-    pub command: Vec<String>,
+    pub command: CommandLine,
+    /// Run the commands through a shell, instead of directly
+    pub shell: Option<bool>,
+    /// Working directory of the commands
+    pub cwd: Option<WorkingDir>,
+    /// Environment variables to set for the commands
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Start the commands with an empty environment, except for the variables set by stfed, and
+    /// the ones of `env` and `keep_env`
+    pub clear_env: Option<bool>,
+    /// Environment variables to keep when clearing the environment
+    #[serde(default)]
+    pub keep_env: Vec<String>,
+    /// File to append the output of the command to, besides logging it
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_optional_path")]
+    pub log_file: Option<PathBuf>,
+    /// Size beyond which the log file is rotated
+    pub log_file_max_size: Option<bytesize::ByteSize>,
+    /// Number of rotated log files to keep
+    pub log_file_count: Option<usize>,
+    /// User to run the commands as, by name or id
+    pub user: Option<String>,
+    /// Primary group to run the commands with, by name or id
+    pub group: Option<String>,
+    /// Supplementary groups to run the commands with, by name or id
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_one_or_many")]
+    pub groups: Vec<String>,
+    /// Resolved user and groups, if any is set
+    #[serde(skip)]
+    pub credentials: Option<crate::credentials::Credentials>,
+    /// File mode creation mask of the commands
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_umask")]
+    pub umask: Option<nix::sys::stat::Mode>,
+    /// Niceness of the commands, from -20 (highest priority) to 19 (lowest)
+    pub nice: Option<i32>,
+    /// I/O scheduling class of the commands
+    pub ionice_class: Option<IoniceClass>,
+    /// I/O scheduling priority of the commands in their class, from 0 (highest) to 7 (lowest)
+    pub ionice_level: Option<u8>,
+    /// Maximum virtual memory size of each command process
+    pub max_memory: Option<bytesize::ByteSize>,
+    /// Maximum CPU time of each command process
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub max_cpu_time: Option<Duration>,
+    /// Maximum number of open files of each command process
+    pub max_open_files: Option<u64>,
+    /// Maximum number of processes of the user of the commands
+    pub max_processes: Option<u64>,
+    /// Input of the command
+    pub stdin: Option<Stdin>,
+    /// Fetch the information of the event file from the server, to pass it to the command
+    pub enrich: Option<bool>,
     /// Allow concurrent runs for the same hook
     pub allow_concurrent: Option<bool>,
+    /// What to do with runs for events coming while the hook is already running
+    pub on_busy: Option<OnBusy>,
+    /// Key of the runs that can not run concurrently, if not allowed
+    #[serde(default)]
+    pub concurrency_key: ConcurrencyKey,
+    /// Priority of the runs waiting for a free slot, higher first
+    #[serde(default)]
+    pub priority: i32,
+    /// Maximum duration of the hook command, after which its process group is terminated
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub timeout: Option<Duration>,
+    /// Time to wait for the hook processes to exit after terminating them on timeout, before
+    /// killing them
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub timeout_grace: Option<Duration>,
+    /// Policy to run the hook command again when it fails
+    pub retry: Option<RetryPolicy>,
+}
+
+/// Retry policy of a hook whose command fails
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct RetryPolicy {
+    /// Maximum number of attempts, including the first run
+    pub attempts: u32,
+    /// Delay before the first retry, doubled after each retry
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub backoff: Option<Duration>,
+    /// Maximum delay between retries
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub max_backoff: Option<Duration>,
+    /// Exit codes to retry on, any failure if empty
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_one_or_many")]
+    pub exit_codes: Vec<i32>,
+}
+
+/// Separator of the paths of collected events
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PathsSeparator {
+    /// Newline, for line oriented tools
+    #[default]
+    Newline,
+    /// NUL byte, for paths that can contain newlines
+    Nul,
+}
+
+impl PathsSeparator {
+    /// Separator byte
+    pub(crate) fn byte(self) -> u8 {
+        match self {
+            Self::Newline => b'\n',
+            Self::Nul => b'\0',
+        }
+    }
+}
+
+/// Input of a hook command
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Stdin {
+    /// Nothing
+    #[default]
+    Null,
+    /// JSON document describing the event
+    Json,
+}
+
+/// Policy for runs of a hook for events coming while it is already running
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum OnBusy {
+    /// Drop the run
+    #[default]
+    Skip,
+    /// Run once after the running process exits, for the latest event
+    QueueOne,
+    /// Run for each event, one after the other
+    QueueAll,
+}
+
+/// Key of the runs of a hook that exclude each other
+#[derive(Debug, Default)]
+pub(crate) enum ConcurrencyKey {
+    /// Any run of the hook
+    #[default]
+    Hook,
+    /// Runs for the same file
+    Path,
+    /// Runs for the same folder
+    Folder,
+    /// Runs with the same rendered template
+    Template(crate::template::Template),
+}
+
+impl FromStr for ConcurrencyKey {
+    type Err = crate::template::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hook" => Ok(Self::Hook),
+            "path" => Ok(Self::Path),
+            "folder" => Ok(Self::Folder),
+            _ => s.parse().map(Self::Template),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ConcurrencyKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(|err| {
+            serde::de::Error::custom(format!("Invalid concurrency key {s:?}: {err}"))
+        })
+    }
+}
+
+/// I/O scheduling class of the commands of a hook
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum IoniceClass {
+    /// Served first, whatever the other processes
+    Realtime,
+    /// Served by priority level, fairly with the other processes
+    #[default]
+    BestEffort,
+    /// Served only when no other process needs the disk
+    Idle,
+}
+
+/// Command of a hook, with placeholders substituted for each run
+#[derive(Clone, Eq, PartialEq)]
+pub(crate) struct CommandLine {
+    /// Arguments, split as a shell would, to run the command directly
+    pub args: Vec<crate::template::Template>,
+    /// Whole command, to run it through a shell
+    pub script: crate::template::Template,
+}
+
+impl FromStr for CommandLine {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let args = shlex::split(s).ok_or_else(|| anyhow::anyhow!("Invalid command: {s:?}"))?;
+        // An empty command would panic at hook run time
+        anyhow::ensure!(!args.is_empty(), "Empty command");
+        let args = args
+            .iter()
+            .map(|a| {
+                a.parse()
+                    .with_context(|| format!("Invalid command argument {a:?}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            args,
+            script: s.parse()?,
+        })
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for CommandLine {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|err: anyhow::Error| serde::de::Error::custom(format!("{err:#}")))
+    }
+}
+
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.script.fmt(f)
+    }
+}
+
+// Shown as its arguments, as they are run
+impl fmt::Debug for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.args, f)
+    }
+}
+
+/// Working directory of the commands of a hook
+#[derive(Debug)]
+pub(crate) enum WorkingDir {
+    /// Local path of the folder of the event
+    Folder,
+    /// Path, relative to the folder of the event if not absolute
+    Path(PathBuf),
+}
+
+impl<'de> serde::Deserialize<'de> for WorkingDir {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        if s == "folder" {
+            return Ok(Self::Folder);
+        }
+        expand_tilde(&s)
+            .map(Self::Path)
+            .ok_or_else(|| serde::de::Error::custom(format!("Invalid cwd {s:?}: User not found")))
+    }
+}
+
+/// Syncthing folder selection of a hook
+#[derive(Debug)]
+pub(crate) enum FolderSelector {
+    /// Local paths or path globs of the folders
+    Paths(Vec<FolderPattern>),
+    /// Syncthing folder ids, resolved against the server configuration
+    Ids(Vec<String>),
+    /// Syncthing folder labels, resolved against the server configuration
+    Labels(Vec<String>),
+}
+
+impl FolderSelector {
+    /// Subdirectory selected in the Syncthing folder with the given id, label, and normalized
+    /// local path, relative to it and empty for the whole folder, or `None` if the selection
+    /// does not include it
+    pub(crate) fn subdir(&self, id: &str, label: &str, path: &NormalizedPath) -> Option<PathBuf> {
+        match self {
+            Self::Paths(patterns) => patterns.iter().find_map(|p| p.subdir(path)),
+            Self::Ids(ids) => ids.iter().any(|i| i == id).then(PathBuf::new),
+            Self::Labels(labels) => labels.iter().any(|l| l == label).then(PathBuf::new),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for FolderSelector {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        /// Folder selection keys of a hook
+        #[derive(serde::Deserialize)]
+        struct Keys {
+            /// See `FolderSelector::Paths`
+            folder: Option<OneOrMany<FolderPattern>>,
+            /// See `FolderSelector::Ids`
+            folder_id: Option<OneOrMany<String>>,
+            /// See `FolderSelector::Labels`
+            folder_label: Option<OneOrMany<String>>,
+        }
+
+        let keys = Keys::deserialize(deserializer)?;
+        match (keys.folder, keys.folder_id, keys.folder_label) {
+            (Some(patterns), None, None) => Ok(Self::Paths(patterns.into())),
+            (None, Some(ids), None) => Ok(Self::Ids(ids.into())),
+            (None, None, Some(labels)) => Ok(Self::Labels(labels.into())),
+            _ => Err(serde::de::Error::custom(
+                "Exactly one of folder, folder_id or folder_label must be set",
+            )),
+        }
+    }
+}
+
+/// Single value, or list of values
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    /// Single value
+    One(T),
+    /// List of values
+    Many(Vec<T>),
+}
+
+impl<T> From<OneOrMany<T>> for Vec<T> {
+    fn from(val: OneOrMany<T>) -> Self {
+        match val {
+            OneOrMany::One(v) => vec![v],
+            OneOrMany::Many(v) => v,
+        }
+    }
+}
+
+/// Local folder path, or path glob
+#[derive(Debug)]
+pub(crate) enum FolderPattern {
+    /// Any folder, ie. `*`
+    Any,
+    /// Absolute path of the folder, or of a directory within it
+    Path(NormalizedPath),
+    /// Glob matching absolute folder paths
+    Glob(Box<globset::GlobMatcher>),
+}
+
+impl FolderPattern {
+    /// Subdirectory the pattern matches in a folder with a normalized path, see
+    /// `FolderSelector::subdir`
+    fn subdir(&self, path: &NormalizedPath) -> Option<PathBuf> {
+        match self {
+            Self::Any => Some(PathBuf::new()),
+            Self::Path(p) => p.strip_prefix(path).ok().map(Path::to_path_buf),
+            Self::Glob(g) => g.is_match(path).then(PathBuf::new),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for FolderPattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        /// Characters making a path component a glob
+        const GLOB_CHARS: [char; 4] = ['*', '?', '[', '{'];
+
+        let s = String::deserialize(deserializer)?;
+        if s == "*" {
+            return Ok(Self::Any);
+        }
+        // Existing paths are literal, as they were before globs were supported, to keep matching
+        // folders like "Photos [2024]"
+        if !s.contains(GLOB_CHARS) || expand_tilde(&s).is_some_and(|p| p.exists()) {
+            return Path::new(&s)
+                .try_into()
+                .map(Self::Path)
+                .map_err(serde::de::Error::custom);
+        }
+
+        // Normalize the literal components preceding the first glob one, the same way folder
+        // paths are, otherwise a symlink in them would prevent any match
+        let path = expand_tilde(&s).ok_or_else(|| serde::de::Error::custom("User not found"))?;
+        let mut components = path.components().peekable();
+        let mut prefix = PathBuf::new();
+        while let Some(component) =
+            components.next_if(|c| !c.as_os_str().to_string_lossy().contains(GLOB_CHARS))
+        {
+            prefix.push(component);
+        }
+        let prefix = prefix.canonicalize().unwrap_or(prefix);
+        let glob = components.fold(
+            globset::escape(&prefix.to_string_lossy()),
+            |mut glob, component| {
+                if !glob.is_empty() && !glob.ends_with('/') {
+                    glob.push('/');
+                }
+                glob.push_str(&component.as_os_str().to_string_lossy());
+                glob
+            },
+        );
+        globset::GlobBuilder::new(&glob)
+            .literal_separator(true)
+            .build()
+            .map(|g| Self::Glob(Box::new(g.compile_matcher())))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl FolderHook {
+    /// Name of the hook, or its command if not set
+    pub(crate) fn name(&self) -> Cow<'_, str> {
+        self.name
+            .as_deref()
+            .map_or_else(|| Cow::Owned(self.command.to_string()), Cow::Borrowed)
+    }
+
+    /// Resolve the user and groups to run the commands as, checking they can be switched to
+    fn resolve_credentials(&mut self) -> anyhow::Result<()> {
+        self.credentials = crate::credentials::Credentials::resolve(
+            self.user.as_deref(),
+            self.group.as_deref(),
+            &self.groups,
+        )?;
+        if let Some(credentials) = &self.credentials {
+            credentials.check_allowed()?;
+        }
+        Ok(())
+    }
+
+    /// Check the scheduling priority and resource limits are valid, and supported
+    fn check_limits(&self) -> anyhow::Result<()> {
+        if let Some(nice) = self.nice {
+            anyhow::ensure!(
+                (-20..=19).contains(&nice),
+                "Invalid nice value {nice}, must be from -20 to 19"
+            );
+            anyhow::ensure!(
+                nice >= 0 || crate::credentials::has_capability(crate::credentials::CAP_SYS_NICE),
+                "Negative nice value {nice} requires root or the CAP_SYS_NICE capability"
+            );
+        }
+        if let Some(level) = self.ionice_level {
+            anyhow::ensure!(
+                level <= 7,
+                "Invalid ionice level {level}, must be from 0 to 7"
+            );
+        }
+        if cfg!(not(target_os = "linux")) {
+            anyhow::ensure!(
+                self.ionice_class.is_none()
+                    && self.ionice_level.is_none()
+                    && self.max_processes.is_none(),
+                "ionice_class, ionice_level and max_processes are only supported on Linux"
+            );
+        }
+        Ok(())
+    }
+
+    /// Check the options of the hook apply to its event
+    fn check_event_options(&self) -> anyhow::Result<()> {
+        // Only changes synced down are made by a remote device
+        anyhow::ensure!(
+            self.modified_by.is_empty()
+                || matches!(
+                    self.event,
+                    FolderEvent::FileDownSyncDone | FolderEvent::RemoteFileConflict
+                ),
+            "modified_by is not supported for {} events",
+            self.event.name()
+        );
+        Ok(())
+    }
+
+    /// Compile the path filters again, with the matching options of the hook
+    fn apply_match_options(&mut self) -> anyhow::Result<()> {
+        let normalize_unicode = self.normalize_unicode.unwrap_or(false);
+        let case_insensitive = self.case_insensitive.unwrap_or(false);
+        if !normalize_unicode && !case_insensitive {
+            return Ok(());
+        }
+        for globs in [&mut self.filter, &mut self.exclude].into_iter().flatten() {
+            *globs = GlobList::new(
+                mem::take(&mut globs.globs),
+                normalize_unicode,
+                case_insensitive,
+            )?;
+        }
+        if let Some(filter_regex) = &mut self.filter_regex {
+            *filter_regex =
+                regex::RegexBuilder::new(&nfc(filter_regex.as_str(), normalize_unicode))
+                    .case_insensitive(case_insensitive)
+                    .build()?;
+        }
+        Ok(())
+    }
+
+    /// Match a path relative to the hook folder against the filters of the hook, returning the
+    /// named groups captured by its regex filter, or `None` if filtered out
+    pub(crate) fn match_path(&self, path: &Path) -> Option<Vec<(String, String)>> {
+        let path = match path.to_str() {
+            Some(s) if self.normalize_unicode.unwrap_or(false) => {
+                Cow::Owned(PathBuf::from(nfc(s, true).into_owned()))
+            }
+            _ => Cow::Borrowed(path),
+        };
+        // See `GlobList::new`
+        let glob_path = match path.to_str() {
+            Some(s) if self.case_insensitive.unwrap_or(false) => {
+                Cow::Owned(PathBuf::from(s.to_lowercase()))
+            }
+            _ => Cow::Borrowed(path.as_ref()),
+        };
+        // Only prepare the path once for both glob sets
+        let candidate = globset::Candidate::new(&glob_path);
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|f| !f.is_match_candidate(&candidate))
+            || self
+                .exclude
+                .as_ref()
+                .is_some_and(|e| e.is_match_candidate(&candidate))
+        {
+            return None;
+        }
+        let Some(filter_regex) = &self.filter_regex else {
+            return Some(Vec::new());
+        };
+        let captures = filter_regex.captures(path.to_str()?)?;
+        Some(
+            filter_regex
+                .capture_names()
+                .flatten()
+                .filter_map(|name| {
+                    Some((name.to_owned(), captures.name(name)?.as_str().to_owned()))
+                })
+                .collect(),
+        )
+    }
+}
+
+/// String normalized to Unicode NFC if `normalize`
+fn nfc(s: &str, normalize: bool) -> Cow<'_, str> {
+    if normalize {
+        Cow::Owned(s.nfc().collect())
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+/// Globs compiled into a set, matching if any of them matches
+#[derive(Debug)]
+pub(crate) struct GlobList {
+    /// Glob expressions, to compile them again with other matching options
+    globs: Vec<String>,
+    /// Compiled glob set
+    set: globset::GlobSet,
+}
+
+impl GlobList {
+    /// Compile globs, optionally normalized to Unicode NFC, or ignoring case
+    fn new(
+        globs: Vec<String>,
+        normalize_unicode: bool,
+        case_insensitive: bool,
+    ) -> Result<Self, globset::Error> {
+        // Matching all globs of a set is done in a single pass, whatever their count
+        let mut builder = globset::GlobSetBuilder::new();
+        for glob in &globs {
+            let glob = nfc(glob, normalize_unicode);
+            // globset only ignores ASCII case, so globs and paths are also lowercased to ignore
+            // the case of other characters
+            let glob = if case_insensitive {
+                Cow::Owned(glob.to_lowercase())
+            } else {
+                glob
+            };
+            builder.add(
+                globset::GlobBuilder::new(&glob)
+                    .literal_separator(true)
+                    .case_insensitive(case_insensitive)
+                    .build()?,
+            );
+        }
+        Ok(Self {
+            set: builder.build()?,
+            globs,
+        })
+    }
+}
+
+impl Deref for GlobList {
+    type Target = globset::GlobSet;
+
+    fn deref(&self) -> &Self::Target {
+        &self.set
+    }
+}
+
+/// Deserialize a glob, or a list of globs into a glob set to validate glob expressions
+fn deserialize_globs<'de, D>(deserializer: D) -> Result<Option<GlobList>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let opt: Option<OneOrMany<String>> = Option::deserialize(deserializer)?;
+    opt.map(|globs| GlobList::new(globs.into(), false, false).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Deserialize hooks, compiling their path filters with their matching options, which serde
+/// can not pass to the filter deserializers
+fn deserialize_hooks<'de, D>(deserializer: D) -> Result<Vec<FolderHook>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut hooks: Vec<FolderHook> = Vec::deserialize(deserializer)?;
+    for hook in &mut hooks {
+        hook.apply_match_options()
+            .map_err(serde::de::Error::custom)?;
+        hook.resolve_credentials()
+            .map_err(|err| serde::de::Error::custom(format!("{err:#}")))?;
+        hook.check_limits().map_err(serde::de::Error::custom)?;
+        hook.check_event_options()
+            .map_err(serde::de::Error::custom)?;
+    }
+    Ok(hooks)
 }
 
-/// Deserialize filter into a glob matcher to validate glob expression
-fn deserialize_glob<'de, D>(deserializer: D) -> Result<Option<globset::GlobMatcher>, D::Error>
+/// Deserialize optional octal file mode creation mask
+fn deserialize_umask<'de, D>(deserializer: D) -> Result<Option<nix::sys::stat::Mode>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
     let opt: Option<String> = Option::deserialize(deserializer)?;
     opt.map(|s| {
-        globset::GlobBuilder::new(&s)
-            .literal_separator(true)
-            .build()
-            .map(|g| g.compile_matcher())
-            .map_err(serde::de::Error::custom)
+        nix::libc::mode_t::from_str_radix(&s, 8)
+            .ok()
+            .filter(|bits| *bits <= 0o777)
+            .and_then(nix::sys::stat::Mode::from_bits)
+            .ok_or_else(|| serde::de::Error::custom(format!("Invalid umask {s:?}")))
     })
     .transpose()
 }
 
-/// Deserialize command string into a vec directly usable by `std::Command`
-fn deserialize_command<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+/// Deserialize optional path, with ~ replaced
+fn deserialize_optional_path<'de, D>(deserializer: D) -> Result<Option<PathBuf>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let s = String::deserialize(deserializer)?;
-    let command = shlex::split(&s)
-        .ok_or_else(|| serde::de::Error::custom(format!("Invalid command: {s:?}")))?;
-    // An empty command would panic at hook run time
-    if command.is_empty() {
-        return Err(serde::de::Error::custom("Empty command"));
-    }
-    Ok(command)
+    let opt: Option<String> = Option::deserialize(deserializer)?;
+    opt.map(|s| {
+        expand_tilde(&s)
+            .ok_or_else(|| serde::de::Error::custom(format!("Invalid path {s:?}: User not found")))
+    })
+    .transpose()
+}
+
+/// Deserialize a single value, or a list of values
+fn deserialize_one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    OneOrMany::deserialize(deserializer).map(Vec::from)
+}
+
+/// Deserialize a human readable duration, ie. `1h 30m`
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let opt: Option<String> = Option::deserialize(deserializer)?;
+    opt.map(|s| humantime::parse_duration(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Deserialize filter into a regex anchored at both ends, to validate regex expression
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Option<regex::Regex>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let opt: Option<String> = Option::deserialize(deserializer)?;
+    opt.map(|s| regex::Regex::new(&format!("^(?:{s})$")).map_err(serde::de::Error::custom))
+        .transpose()
 }
 
 /// Folder event kind
-#[derive(Clone, Debug, Eq, Hash, PartialEq, serde::Deserialize)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum FolderEvent {
     /// A whole folder has been synced down
@@ -165,6 +890,25 @@ pub(crate) enum FolderEvent {
     RemoteFileConflict,
 }
 
+impl FolderEvent {
+    /// Name of the event kind, as in the configuration
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::FolderDownSyncDone => "folder_down_sync_done",
+            Self::FileDownSyncDone => "file_down_sync_done",
+            Self::FileConflict => "file_conflict",
+            Self::RemoteFileConflict => "remote_file_conflict",
+        }
+    }
+}
+
+/// Path of a state file, creating its parent directory if needed
+pub(crate) fn state_filepath(filename: &str) -> anyhow::Result<PathBuf> {
+    xdg::BaseDirectories::with_prefix(env!("CARGO_PKG_NAME"))
+        .place_state_file(filename)
+        .with_context(|| format!("Unable to create state directory for {filename:?}"))
+}
+
 /// Parse local configuration
 pub(crate) fn parse() -> anyhow::Result<(Config, FolderConfig)> {
     let binary_name = env!("CARGO_PKG_NAME");
@@ -229,6 +973,14 @@ mod tests {
             event = "remote_file_conflict"
             command = "true"
             allow_concurrent = true
+            shell = true
+            cwd = "folder"
+            env = {{ LANG = "C" }}
+            clear_env = true
+            keep_env = ["HOME"]
+            log_file = "/var/log/stfed/conflicts.log"
+            log_file_max_size = "1 MiB"
+            log_file_count = 3
             "#,
             folder = folder.to_str().unwrap()
         );
@@ -236,21 +988,356 @@ mod tests {
         let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
 
         assert_eq!(hooks.hooks.len(), 2);
-        assert_eq!(hooks.hooks[0].folder, NormalizedPath(folder.clone()));
+        assert_eq!(
+            hooks.hooks[0]
+                .folder
+                .subdir("id", "label", &NormalizedPath(folder.clone())),
+            Some(PathBuf::new())
+        );
         assert_eq!(hooks.hooks[0].event, FolderEvent::FileDownSyncDone);
         assert_eq!(
-            hooks.hooks[0].command,
+            hooks.hooks[0]
+                .command
+                .args
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
             ["notify-send", "stfed event", "body"]
         );
         assert_eq!(hooks.hooks[0].allow_concurrent, None);
         assert!(hooks.hooks[0].filter.is_some());
-        assert_eq!(hooks.hooks[1].folder, NormalizedPath(folder));
+        assert_eq!(
+            hooks.hooks[1]
+                .folder
+                .subdir("id", "label", &NormalizedPath(folder)),
+            Some(PathBuf::new())
+        );
         assert_eq!(hooks.hooks[1].event, FolderEvent::RemoteFileConflict);
-        assert_eq!(hooks.hooks[1].command, ["true"]);
+        assert_eq!(hooks.hooks[1].command.to_string(), "true");
         assert_eq!(hooks.hooks[1].allow_concurrent, Some(true));
+        assert_eq!(hooks.hooks[1].shell, Some(true));
+        assert!(matches!(hooks.hooks[1].cwd, Some(WorkingDir::Folder)));
+        assert_eq!(hooks.hooks[1].env["LANG"], "C");
+        assert_eq!(hooks.hooks[1].clear_env, Some(true));
+        assert_eq!(hooks.hooks[1].keep_env, ["HOME"]);
+        assert_eq!(
+            hooks.hooks[1].log_file.as_deref(),
+            Some(Path::new("/var/log/stfed/conflicts.log"))
+        );
+        assert_eq!(
+            hooks.hooks[1].log_file_max_size,
+            Some(bytesize::ByteSize::mib(1))
+        );
+        assert_eq!(hooks.hooks[1].log_file_count, Some(3));
         assert!(hooks.hooks[1].filter.is_none());
     }
 
+    /// User, priority and resource limit options of the hook processes
+    #[test]
+    fn parse_process_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            command = "true"
+            user = "{user}"
+            umask = "027"
+            nice = 10
+            ionice_class = "idle"
+            max_memory = "2 GiB"
+            max_cpu_time = "10m"
+            max_open_files = 256
+            max_processes = 64
+            "#,
+            folder = dir.path().to_str().unwrap(),
+            user = nix::unistd::Uid::effective()
+        );
+
+        let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
+
+        let hook = &hooks.hooks[0];
+        assert!(hook.credentials.is_some());
+        assert_eq!(
+            hook.umask,
+            Some(nix::sys::stat::Mode::from_bits_truncate(0o027))
+        );
+        assert_eq!(hook.nice, Some(10));
+        assert_eq!(hook.ionice_class, Some(IoniceClass::Idle));
+        assert_eq!(hook.ionice_level, None);
+        assert_eq!(hook.max_memory, Some(bytesize::ByteSize::gib(2)));
+        assert_eq!(hook.max_cpu_time, Some(Duration::from_secs(10 * 60)));
+        assert_eq!(hook.max_open_files, Some(256));
+        assert_eq!(hook.max_processes, Some(64));
+    }
+
+    /// Raising the priority of hooks must be refused if it is not allowed
+    #[test]
+    fn check_negative_nice_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            command = "true"
+            nice = -5
+            "#,
+            folder = dir.path().to_str().unwrap()
+        );
+
+        let res = toml::from_str::<FolderConfig>(&toml_data);
+
+        assert_eq!(
+            res.is_ok(),
+            crate::credentials::has_capability(crate::credentials::CAP_SYS_NICE)
+        );
+    }
+
+    /// A device filter must be rejected for events not synced down from a device
+    #[test]
+    fn reject_modified_by_of_local_events() {
+        let dir = tempfile::tempdir().unwrap();
+        for (event, valid) in [
+            ("file_down_sync_done", true),
+            ("remote_file_conflict", true),
+            ("file_conflict", false),
+            ("folder_down_sync_done", false),
+        ] {
+            let toml_data = format!(
+                r#"
+                [[hooks]]
+                folder = "{folder}"
+                event = "{event}"
+                command = "true"
+                modified_by = "scanner"
+                "#,
+                folder = dir.path().to_str().unwrap()
+            );
+
+            let res = toml::from_str::<FolderConfig>(&toml_data);
+
+            assert_eq!(res.is_ok(), valid, "{event}: {res:?}");
+        }
+    }
+
+    /// Existing folder paths with glob characters must be matched literally
+    #[test]
+    fn parse_existing_folder_with_glob_chars() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("Photos [2024]");
+        fs::create_dir(&folder).unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            command = "true"
+
+            [[hooks]]
+            folder = "{dir}/Photos [[]2023]"
+            event = "file_down_sync_done"
+            command = "true"
+            "#,
+            folder = folder.to_str().unwrap(),
+            dir = dir.path().to_str().unwrap()
+        );
+
+        let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
+
+        assert!(matches!(
+            &hooks.hooks[0].folder,
+            FolderSelector::Paths(patterns) if matches!(patterns[..], [FolderPattern::Path(_)])
+        ));
+        let FolderSelector::Paths(patterns) = &hooks.hooks[1].folder else {
+            panic!("{:?}", hooks.hooks[1].folder);
+        };
+        let [FolderPattern::Glob(glob)] = &patterns[..] else {
+            panic!("{patterns:?}");
+        };
+        assert!(glob.is_match(dir.path().canonicalize().unwrap().join("Photos [2023]")));
+    }
+
+    /// Folders can be selected by Syncthing id or label instead of local path
+    #[test]
+    fn parse_folder_id_and_label() {
+        let toml_data = r#"
+            [[hooks]]
+            folder_id = "abcd-1234"
+            event = "folder_down_sync_done"
+            command = "true"
+
+            [[hooks]]
+            folder_label = "Photos"
+            event = "folder_down_sync_done"
+            command = "true"
+            "#;
+
+        let hooks: FolderConfig = toml::from_str(toml_data).unwrap();
+
+        assert!(
+            matches!(&hooks.hooks[0].folder, FolderSelector::Ids(ids) if ids == &["abcd-1234"])
+        );
+        assert!(
+            matches!(&hooks.hooks[1].folder, FolderSelector::Labels(labels) if labels == &["Photos"])
+        );
+    }
+
+    /// A hook can select several folders, with a list, path globs, or a wildcard
+    #[test]
+    fn folder_selector_matches_several_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let projects = dir.path().join("projects");
+        let other = dir.path().join("other");
+        for path in [projects.join("a"), projects.join("b"), other.clone()] {
+            fs::create_dir_all(path).unwrap();
+        }
+        let path = |p: &Path| NormalizedPath::try_from(p).unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "*"
+            event = "file_conflict"
+            command = "true"
+
+            [[hooks]]
+            folder = ["{other}", "{projects}/*"]
+            event = "file_conflict"
+            command = "true"
+
+            [[hooks]]
+            folder_label = ["Photos", "Music"]
+            event = "file_conflict"
+            command = "true"
+            "#,
+            other = other.to_str().unwrap(),
+            projects = projects.to_str().unwrap()
+        );
+
+        let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
+
+        assert_eq!(
+            hooks.hooks[0].folder.subdir("id", "label", &path(&other)),
+            Some(PathBuf::new())
+        );
+        let by_path = &hooks.hooks[1].folder;
+        assert_eq!(
+            by_path.subdir("id", "label", &path(&other)),
+            Some(PathBuf::new())
+        );
+        assert_eq!(
+            by_path.subdir("id", "label", &path(&projects.join("a"))),
+            Some(PathBuf::new())
+        );
+        assert_eq!(
+            by_path.subdir("id", "label", &path(&projects.join("b"))),
+            Some(PathBuf::new())
+        );
+        assert!(by_path.subdir("id", "label", &path(&projects)).is_none());
+        assert_eq!(
+            by_path.subdir("id", "label", &path(dir.path())),
+            Some(PathBuf::from("other"))
+        );
+        let by_label = &hooks.hooks[2].folder;
+        assert_eq!(
+            by_label.subdir("id", "Music", &path(&other)),
+            Some(PathBuf::new())
+        );
+        assert!(by_label.subdir("id", "Videos", &path(&other)).is_none());
+    }
+
+    /// Folder globs must match the normalized folder paths, even through a symlink
+    #[test]
+    fn folder_glob_normalizes_literal_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real");
+        fs::create_dir_all(real.join("a")).unwrap();
+        let link = dir.path().join("link");
+        symlink(&real, &link).unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "{link}/*"
+            event = "file_conflict"
+            command = "true"
+            "#,
+            link = link.to_str().unwrap()
+        );
+
+        let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
+
+        let folder = NormalizedPath::try_from(real.join("a").as_path()).unwrap();
+        assert_eq!(
+            hooks.hooks[0].folder.subdir("id", "label", &folder),
+            Some(PathBuf::new())
+        );
+    }
+
+    /// A directory within a folder must select the folder, scoped to that directory
+    #[test]
+    fn folder_path_selects_subdirectory() {
+        let dir = tempfile::tempdir().unwrap();
+        let invoices = dir.path().join("docs/invoices");
+        fs::create_dir_all(&invoices).unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "{invoices}"
+            event = "file_down_sync_done"
+            command = "true"
+            "#,
+            invoices = invoices.to_str().unwrap()
+        );
+
+        let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
+
+        let path = |p: &Path| NormalizedPath::try_from(p).unwrap();
+        let selector = &hooks.hooks[0].folder;
+        assert_eq!(
+            selector.subdir("id", "label", &path(dir.path())),
+            Some(PathBuf::from("docs/invoices"))
+        );
+        assert_eq!(
+            selector.subdir("id", "label", &path(&invoices)),
+            Some(PathBuf::new())
+        );
+        assert_eq!(
+            selector.subdir("id", "label", &path(&dir.path().join("docs"))),
+            Some(PathBuf::from("invoices"))
+        );
+        fs::create_dir(dir.path().join("other")).unwrap();
+        assert!(
+            selector
+                .subdir("id", "label", &path(&dir.path().join("other")))
+                .is_none()
+        );
+    }
+
+    /// A hook must select its folder in exactly one way
+    #[test]
+    fn reject_ambiguous_or_missing_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let ambiguous = format!(
+            r#"
+            [[hooks]]
+            folder = "{folder}"
+            folder_label = "Photos"
+            event = "folder_down_sync_done"
+            command = "true"
+            "#,
+            folder = dir.path().to_str().unwrap()
+        );
+        let missing = r#"
+            [[hooks]]
+            event = "folder_down_sync_done"
+            command = "true"
+            "#;
+
+        assert!(toml::from_str::<FolderConfig>(&ambiguous).is_err());
+        assert!(toml::from_str::<FolderConfig>(missing).is_err());
+    }
+
     /// Filter glob wildcards must not cross directory separators
     #[test]
     fn filter_glob_does_not_cross_directories() {
@@ -273,6 +1360,81 @@ mod tests {
         assert!(!filter.is_match("sub/report.pdf"));
     }
 
+    /// A path must match any of the filter globs, and none of the exclusion ones
+    #[test]
+    fn filter_and_exclude_glob_lists() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            filter = ["*.jpg", "*.png", "**/*.md"]
+            exclude = ["drafts/**"]
+            command = "true"
+
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            exclude = "*.tmp"
+            command = "true"
+            "#,
+            folder = dir.path().to_str().unwrap()
+        );
+
+        let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
+
+        let filtered = &hooks.hooks[0];
+        assert!(filtered.match_path(Path::new("a.jpg")).is_some());
+        assert!(filtered.match_path(Path::new("a.png")).is_some());
+        assert!(filtered.match_path(Path::new("notes/a.md")).is_some());
+        assert!(filtered.match_path(Path::new("drafts/a.md")).is_none());
+        assert!(filtered.match_path(Path::new("a.gif")).is_none());
+        let excluded = &hooks.hooks[1];
+        assert!(excluded.match_path(Path::new("a.txt")).is_some());
+        assert!(excluded.match_path(Path::new("a.tmp")).is_none());
+    }
+
+    /// A regex filter must match the whole path, and return its named captures
+    #[test]
+    fn filter_regex_captures() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            filter = "**/*.pdf"
+            filter_regex = 'invoices/(?P<year>\d{{4}})/(?P<month>\d{{2}})/(\w+)\.pdf'
+            command = "true"
+            "#,
+            folder = dir.path().to_str().unwrap()
+        );
+
+        let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
+
+        let hook = &hooks.hooks[0];
+        assert_eq!(
+            hook.match_path(Path::new("invoices/2026/03/acme.pdf")),
+            Some(vec![
+                ("year".to_owned(), "2026".to_owned()),
+                ("month".to_owned(), "03".to_owned())
+            ])
+        );
+        assert!(
+            hook.match_path(Path::new("old/invoices/2026/03/acme.pdf"))
+                .is_none()
+        );
+        assert!(
+            hook.match_path(Path::new("invoices/2026/3/acme.pdf"))
+                .is_none()
+        );
+        assert!(
+            hook.match_path(Path::new("invoices/2026/03/acme.txt"))
+                .is_none()
+        );
+    }
+
     /// An unparseable command string must be rejected when parsing hooks
     #[test]
     fn reject_invalid_command() {
@@ -290,6 +1452,54 @@ mod tests {
         assert!(toml::from_str::<FolderConfig>(&toml_data).is_err());
     }
 
+    /// A hook command with an unknown placeholder must be rejected when parsing hooks
+    #[test]
+    fn reject_unknown_command_placeholder() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            command = "cp {{path}} /backup/{{file}}"
+            "#,
+            folder = dir.path().to_str().unwrap()
+        );
+
+        let err = toml::from_str::<FolderConfig>(&toml_data).unwrap_err();
+        assert!(
+            err.to_string().contains("Unknown placeholder {file}"),
+            "{err}"
+        );
+    }
+
+    /// Braces that are not placeholders must be kept as is in hook commands, as in configurations
+    /// written before placeholders
+    #[test]
+    fn keep_non_placeholder_braces_in_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            command = "sh -c \"awk '{{print $1}}' {{path}} > ${{HOME}}/{{{{file}}}}\""
+            "#,
+            folder = dir.path().to_str().unwrap()
+        );
+
+        let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
+
+        assert_eq!(
+            hooks.hooks[0].command.to_string(),
+            "sh -c \"awk '{print $1}' {path} > ${HOME}/{{file}}\""
+        );
+        assert_eq!(
+            hooks.hooks[0].command.args[2].to_string(),
+            "awk '{print $1}' {path} > ${HOME}/{{file}}"
+        );
+    }
+
     /// An empty hook command must be rejected when parsing hooks, instead of panicking
     /// when the hook first runs
     #[test]
@@ -308,22 +1518,139 @@ mod tests {
         assert!(toml::from_str::<FolderConfig>(&toml_data).is_err());
     }
 
-    /// An invalid filter glob must be rejected when parsing hooks
+    /// Path filters must match composed and decomposed Unicode forms, or ignore case, only if
+    /// enabled
     #[test]
-    fn reject_invalid_filter_glob() {
+    fn unicode_normalized_and_case_insensitive_filters() {
         let dir = tempfile::tempdir().unwrap();
         let toml_data = format!(
             r#"
             [[hooks]]
             folder = "{folder}"
             event = "file_down_sync_done"
-            filter = "[oops"
+            filter = "Résumé*.pdf"
+            command = "true"
+
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            filter = "Résumé*.pdf"
+            exclude = "*draft*"
+            filter_regex = 'Résumé (?P<year>\d{{4}}).*'
+            normalize_unicode = true
+            case_insensitive = true
             command = "true"
             "#,
             folder = dir.path().to_str().unwrap()
         );
+        // Decomposed form, as created on macOS
+        let nfd = Path::new("Re\u{301}sume\u{301} 2026.pdf");
+        let nfd_upper = Path::new("RE\u{301}SUME\u{301} 2026.PDF");
 
-        assert!(toml::from_str::<FolderConfig>(&toml_data).is_err());
+        let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
+
+        let strict = &hooks.hooks[0];
+        assert!(
+            strict
+                .match_path(Path::new("R\u{e9}sum\u{e9} 2026.pdf"))
+                .is_some()
+        );
+        assert!(strict.match_path(nfd).is_none());
+        assert!(strict.match_path(nfd_upper).is_none());
+        let lenient = &hooks.hooks[1];
+        assert_eq!(
+            lenient.match_path(nfd).unwrap(),
+            [("year".to_owned(), "2026".to_owned())]
+        );
+        assert!(lenient.match_path(nfd_upper).is_some());
+        assert!(
+            lenient
+                .match_path(Path::new("R\u{e9}sum\u{e9} 2026 DRAFT.pdf"))
+                .is_none()
+        );
+    }
+
+    /// File conditions must be parsed from human readable sizes and durations
+    #[test]
+    fn parse_file_conditions() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_data = format!(
+            r#"
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            min_size = "100 MB"
+            max_size = 1024
+            max_age = "1h 30m"
+            mime = ["application/pdf", "image/*"]
+            command = "true"
+
+            [[hooks]]
+            folder = "{folder}"
+            event = "file_down_sync_done"
+            mime = "application/pdf"
+            command = "true"
+            "#,
+            folder = dir.path().to_str().unwrap()
+        );
+
+        let hooks: FolderConfig = toml::from_str(&toml_data).unwrap();
+
+        let hook = &hooks.hooks[0];
+        assert_eq!(hook.min_size, Some(bytesize::ByteSize::mb(100)));
+        assert_eq!(hook.max_size, Some(bytesize::ByteSize::b(1024)));
+        assert_eq!(hook.max_age, Some(Duration::from_secs(90 * 60)));
+        assert_eq!(hook.mime, ["application/pdf", "image/*"]);
+        assert_eq!(hooks.hooks[1].mime, ["application/pdf"]);
+    }
+
+    /// An invalid filter glob or regex, or file condition must be rejected when parsing hooks
+    #[test]
+    fn reject_invalid_filter_or_condition() {
+        let dir = tempfile::tempdir().unwrap();
+        for filter in [
+            r#"filter = "[oops""#,
+            r#"exclude = ["*.md", "[oops"]"#,
+            r#"filter_regex = "(oops""#,
+            r#"max_age = "5 parsecs""#,
+            r#"min_size = "big""#,
+            r#"log_file_max_size = "huge""#,
+            r#"umask = "099""#,
+            "nice = 20",
+            r#"ionice_class = "low""#,
+            "ionice_level = 8",
+            r#"max_memory = "lots""#,
+            r#"max_cpu_time = "long""#,
+            r#"umask = "7777""#,
+            r#"user = "no-such-user-stfed""#,
+            r#"groups = ["root", "no-such-group-stfed"]"#,
+            r#"when = "size > \"big\"""#,
+            r#"condition = "'unterminated""#,
+            r#"condition = "test -f {file}""#,
+            r#"condition_timeout = "soon""#,
+            r#"timeout = "-5m""#,
+            r#"retry = { backoff = "30s" }"#,
+            r#"debounce = "soon""#,
+            r#"paths_separator = "tab""#,
+            r#"on_busy = "queue""#,
+            r#"stdin = "yaml""#,
+            r#"concurrency_key = "{file}""#,
+            r#"retry = { attempts = 3, max_backoff = "forever" }"#,
+            r#"schedule = ["22:00-06:00", "25:00-26:00"]"#,
+        ] {
+            let toml_data = format!(
+                r#"
+                [[hooks]]
+                folder = "{folder}"
+                event = "file_down_sync_done"
+                {filter}
+                command = "true"
+                "#,
+                folder = dir.path().to_str().unwrap()
+            );
+
+            assert!(toml::from_str::<FolderConfig>(&toml_data).is_err());
+        }
     }
 
     /// Normalization must resolve symlinks and relative components
@@ -354,6 +1681,27 @@ mod tests {
         assert!(NormalizedPath::try_from(dir.path().join("missing").as_path()).is_err());
     }
 
+    /// Main configuration must be parsed, with an optional maximum of running hooks
+    #[test]
+    fn parse_main_config() {
+        let unlimited: Config =
+            toml::from_str("url = \"http://127.0.0.1:8384/\"\napi_key = \"xyz\"").unwrap();
+        assert!(unlimited.max_concurrent_hooks.is_none());
+
+        let limited: Config = toml::from_str(
+            "url = \"http://127.0.0.1:8384/\"\napi_key = \"xyz\"\nmax_concurrent_hooks = 2",
+        )
+        .unwrap();
+        assert_eq!(limited.max_concurrent_hooks, NonZeroUsize::new(2));
+
+        assert!(
+            toml::from_str::<Config>(
+                "url = \"http://127.0.0.1:8384/\"\napi_key = \"xyz\"\nmax_concurrent_hooks = 0"
+            )
+            .is_err()
+        );
+    }
+
     /// Address and API key must be extracted from a local Syncthing configuration
     #[test]
     fn parse_syncthing_xml_config() {
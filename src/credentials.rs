@@ -0,0 +1,228 @@
+//! User and groups hook commands run as
+
+use std::{fs, io};
+
+use anyhow::Context as _;
+use nix::unistd::{self, Gid, Group, Uid, User};
+
+/// Linux capability to change the user id of processes
+const CAP_SETUID: u32 = 7;
+
+/// Linux capability to change the group ids of processes
+const CAP_SETGID: u32 = 6;
+
+/// Linux capability to raise the scheduling priority of processes
+pub(crate) const CAP_SYS_NICE: u32 = 23;
+
+/// User and groups to switch processes to
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Credentials {
+    /// User id
+    uid: Uid,
+    /// Primary group id
+    gid: Gid,
+    /// Supplementary group ids
+    groups: Vec<Gid>,
+}
+
+impl Credentials {
+    /// Resolve user and group names or ids, `None` if none are set
+    ///
+    /// The user defaults to the current one, the primary group to the one of the user, and the
+    /// supplementary groups to none.
+    pub(crate) fn resolve(
+        user: Option<&str>,
+        group: Option<&str>,
+        groups: &[String],
+    ) -> anyhow::Result<Option<Self>> {
+        if user.is_none() && group.is_none() && groups.is_empty() {
+            return Ok(None);
+        }
+        let user = user.map(resolve_user).transpose()?;
+        let uid = user.as_ref().map_or_else(Uid::effective, |u| u.uid);
+        let gid = match (group, &user) {
+            (Some(group), _) => resolve_group(group)?,
+            (None, Some(user)) => user.gid,
+            (None, None) => Gid::effective(),
+        };
+        let groups = groups
+            .iter()
+            .map(|g| resolve_group(g))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Some(Self { uid, gid, groups }))
+    }
+
+    /// User id
+    pub(crate) fn uid(&self) -> Uid {
+        self.uid
+    }
+
+    /// Check the current process can switch to the credentials
+    pub(crate) fn check_allowed(&self) -> anyhow::Result<()> {
+        for (capability, name) in self.needed_capabilities(Uid::effective(), Gid::effective()) {
+            anyhow::ensure!(
+                has_capability(capability),
+                "Running hooks as user {} and group {} requires root or the {name} capability",
+                self.uid,
+                self.gid
+            );
+        }
+        Ok(())
+    }
+
+    /// Capabilities needed to switch to the credentials from the given current user and group
+    fn needed_capabilities(&self, uid: Uid, gid: Gid) -> Vec<(u32, &'static str)> {
+        let mut capabilities = Vec::new();
+        if self.changes_groups(uid, gid) {
+            capabilities.push((CAP_SETGID, "CAP_SETGID"));
+        }
+        if self.changes_user(uid) {
+            capabilities.push((CAP_SETUID, "CAP_SETUID"));
+        }
+        capabilities
+    }
+
+    /// Whether switching from the given current user and group changes the groups
+    fn changes_groups(&self, uid: Uid, gid: Gid) -> bool {
+        // The supplementary groups are always set when switching user, to not leak the current ones
+        self.gid != gid || !self.groups.is_empty() || self.changes_user(uid)
+    }
+
+    /// Whether switching from the given current user changes it
+    fn changes_user(&self, uid: Uid) -> bool {
+        self.uid != uid
+    }
+
+    /// Switch the current process to the credentials
+    ///
+    /// Only makes async-signal-safe calls, to be called between fork and exec. Only switches what
+    /// changes, so that no capability is needed beyond the ones checked by `check_allowed`.
+    pub(crate) fn apply(&self) -> io::Result<()> {
+        let (uid, gid) = (Uid::effective(), Gid::effective());
+        if self.changes_groups(uid, gid) {
+            set_groups(&self.groups)?;
+            unistd::setgid(self.gid)?;
+        }
+        if self.changes_user(uid) {
+            unistd::setuid(self.uid)?;
+        }
+        Ok(())
+    }
+}
+
+/// Look up a user by name or id
+fn resolve_user(user: &str) -> anyhow::Result<User> {
+    let found = match user.parse() {
+        Ok(uid) => User::from_uid(Uid::from_raw(uid)),
+        Err(_) => User::from_name(user),
+    };
+    found
+        .with_context(|| format!("Failed to look up user {user:?}"))?
+        .with_context(|| format!("Unknown user {user:?}"))
+}
+
+/// Look up a group id by name or id
+fn resolve_group(group: &str) -> anyhow::Result<Gid> {
+    if let Ok(gid) = group.parse() {
+        return Ok(Gid::from_raw(gid));
+    }
+    Ok(Group::from_name(group)
+        .with_context(|| format!("Failed to look up group {group:?}"))?
+        .with_context(|| format!("Unknown group {group:?}"))?
+        .gid)
+}
+
+/// Whether the current process has a capability, or is root where capabilities are not
+/// available
+pub(crate) fn has_capability(capability: u32) -> bool {
+    match fs::read_to_string("/proc/self/status") {
+        Ok(status) => status
+            .lines()
+            .find_map(|l| l.strip_prefix("CapEff:"))
+            .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+            .is_some_and(|caps| caps & (1 << capability) != 0),
+        Err(_) => Uid::effective().is_root(),
+    }
+}
+
+/// Set the supplementary groups of the current process
+#[cfg(not(target_vendor = "apple"))]
+fn set_groups(groups: &[Gid]) -> nix::Result<()> {
+    unistd::setgroups(groups)
+}
+
+/// Set the supplementary groups of the current process
+#[cfg(target_vendor = "apple")]
+fn set_groups(groups: &[Gid]) -> nix::Result<()> {
+    let len = nix::libc::c_int::try_from(groups.len()).map_err(|_| nix::errno::Errno::EINVAL)?;
+    // SAFETY: the pointer and length are the ones of a slice, and `Gid` wraps a `gid_t`
+    let res = unsafe { nix::libc::setgroups(len, groups.as_ptr().cast()) };
+    nix::errno::Errno::result(res).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Users and groups must be resolved by name or id, with the primary group of the user by
+    /// default
+    #[test]
+    fn resolve_credentials() {
+        let root = Credentials::resolve(Some("root"), None, &[])
+            .unwrap()
+            .unwrap();
+        assert_eq!(root.uid, Uid::from_raw(0));
+        assert_eq!(root.gid, Gid::from_raw(0));
+        assert!(root.groups.is_empty());
+
+        let by_id = Credentials::resolve(Some("0"), Some("0"), &["root".to_owned()])
+            .unwrap()
+            .unwrap();
+        assert_eq!(by_id.groups, [Gid::from_raw(0)]);
+
+        assert_eq!(Credentials::resolve(None, None, &[]).unwrap(), None);
+        assert!(Credentials::resolve(Some("no-such-user-stfed"), None, &[]).is_err());
+        assert!(Credentials::resolve(None, Some("no-such-group-stfed"), &[]).is_err());
+    }
+
+    /// Switching the user or groups must need the matching capabilities
+    #[test]
+    fn need_capabilities_to_switch() {
+        let (uid, gid) = (Uid::from_raw(1000), Gid::from_raw(1000));
+        let credentials = |user, group, groups: &[u32]| Credentials {
+            uid: Uid::from_raw(user),
+            gid: Gid::from_raw(group),
+            groups: groups.iter().copied().map(Gid::from_raw).collect(),
+        };
+        let names = |c: &Credentials| -> Vec<_> {
+            c.needed_capabilities(uid, gid)
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect()
+        };
+
+        assert!(names(&credentials(1000, 1000, &[])).is_empty());
+        assert_eq!(names(&credentials(1000, 100, &[])), ["CAP_SETGID"]);
+        assert_eq!(names(&credentials(1000, 1000, &[100])), ["CAP_SETGID"]);
+        assert_eq!(
+            names(&credentials(1001, 1000, &[])),
+            ["CAP_SETGID", "CAP_SETUID"]
+        );
+    }
+
+    /// Switching to the current user and group must succeed without any capability
+    #[test]
+    #[cfg(not(target_vendor = "apple"))]
+    fn apply_current_credentials() {
+        let groups = unistd::getgroups().unwrap();
+        let credentials = Credentials {
+            uid: Uid::effective(),
+            gid: Gid::effective(),
+            groups: Vec::new(),
+        };
+
+        credentials.apply().unwrap();
+
+        assert_eq!(unistd::getgroups().unwrap(), groups);
+    }
+}
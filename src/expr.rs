@@ -0,0 +1,617 @@
+//! Expressions of hook `when` conditions
+
+use std::{fmt, iter::Peekable, str::CharIndices};
+
+/// Error when parsing an expression
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum Error {
+    /// Character not allowed at this position
+    #[error("Unexpected character {0:?} at offset {1}")]
+    UnexpectedChar(char, usize),
+    /// String literal without closing quote
+    #[error("Unterminated string starting at offset {0}")]
+    UnterminatedString(usize),
+    /// Integer literal out of range
+    #[error("Invalid integer {0:?}")]
+    InvalidInteger(String),
+    /// Expression ending prematurely
+    #[error("Unexpected end of expression")]
+    UnexpectedEnd,
+    /// Token not allowed at this position
+    #[error("Unexpected {0}")]
+    UnexpectedToken(String),
+    /// Identifier that is not a context field
+    #[error("Unknown field {0:?}")]
+    UnknownField(String),
+    /// Identifier that is not a function
+    #[error("Unknown function {0:?}")]
+    UnknownFunction(String),
+    /// Function called with a wrong argument count
+    #[error("Function {0:?} expects {1} arguments, got {2}")]
+    ArgumentCount(&'static str, usize, usize),
+    /// Operand of an unexpected type
+    #[error("Expected {expected} operand for {operator:?}, got {found}")]
+    TypeMismatch {
+        /// Operator or function the operand is for
+        operator: &'static str,
+        /// Type the operator needs
+        expected: Type,
+        /// Type of the operand
+        found: Type,
+    },
+}
+
+/// Value type
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Type {
+    /// Boolean
+    Bool,
+    /// Signed integer
+    Int,
+    /// String
+    Str,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Bool => "boolean",
+            Self::Int => "integer",
+            Self::Str => "string",
+        })
+    }
+}
+
+/// Value of an expression
+#[derive(Clone, Debug, Eq, PartialEq, PartialOrd)]
+pub(crate) enum Value {
+    /// Boolean
+    Bool(bool),
+    /// Signed integer
+    Int(i64),
+    /// String
+    Str(String),
+}
+
+/// Field of the event context an expression is evaluated against
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Field {
+    /// Event kind, as in the hooks configuration, ie. `file_down_sync_done`
+    Event,
+    /// Path of the event file relative to the folder, empty for folder events
+    Path,
+    /// Local path of the folder
+    Folder,
+    /// Folder id
+    FolderId,
+    /// Folder label
+    FolderLabel,
+    /// Size of the event file in bytes, 0 for folder events or if it can not be read
+    Size,
+    /// Short id of the device that last modified the event file, empty for folder events or if
+    /// it is unknown
+    Device,
+    /// Error reported by Syncthing with the event, empty if none
+    Error,
+    /// State of the folder reported by Syncthing with the event, ie. `idle`, empty if none
+    State,
+}
+
+impl Field {
+    /// All fields, with their name
+    const ALL: [(&'static str, Self); 9] = [
+        ("event", Self::Event),
+        ("path", Self::Path),
+        ("folder", Self::Folder),
+        ("folder_id", Self::FolderId),
+        ("folder_label", Self::FolderLabel),
+        ("size", Self::Size),
+        ("device", Self::Device),
+        ("error", Self::Error),
+        ("state", Self::State),
+    ];
+
+    /// Type of the values of the field
+    fn type_(self) -> Type {
+        match self {
+            Self::Size => Type::Int,
+            Self::Event
+            | Self::Path
+            | Self::Folder
+            | Self::FolderId
+            | Self::FolderLabel
+            | Self::Device
+            | Self::Error
+            | Self::State => Type::Str,
+        }
+    }
+}
+
+/// Event context fields, computed on demand because some are costly to get
+pub(crate) trait Context {
+    /// Value of a field, which must be of the field type
+    fn value(&self, field: Field) -> Value;
+}
+
+/// Comparison operator
+#[derive(Clone, Copy, Debug)]
+enum Comparison {
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `<`
+    Lt,
+    /// `<=`
+    Le,
+    /// `>`
+    Gt,
+    /// `>=`
+    Ge,
+}
+
+/// String function
+#[derive(Clone, Copy, Debug)]
+enum Function {
+    /// Whether the first string starts with the second
+    StartsWith,
+    /// Whether the first string ends with the second
+    EndsWith,
+    /// Whether the first string contains the second
+    Contains,
+}
+
+impl Function {
+    /// All functions, with their name
+    const ALL: [(&'static str, Self); 3] = [
+        ("starts_with", Self::StartsWith),
+        ("ends_with", Self::EndsWith),
+        ("contains", Self::Contains),
+    ];
+}
+
+/// Node of the syntax tree of an expression
+#[derive(Debug)]
+enum Node {
+    /// Constant value
+    Literal(Value),
+    /// Event context field
+    Field(Field),
+    /// Boolean negation
+    Not(Box<Node>),
+    /// Boolean conjunction, evaluated lazily
+    And(Box<Node>, Box<Node>),
+    /// Boolean disjunction, evaluated lazily
+    Or(Box<Node>, Box<Node>),
+    /// Comparison of two values of the same type
+    Compare(Comparison, Box<Node>, Box<Node>),
+    /// Function call, with 2 string arguments
+    Call(Function, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    /// Evaluate node against an event context
+    fn eval(&self, ctx: &dyn Context) -> Value {
+        match self {
+            Self::Literal(val) => val.clone(),
+            Self::Field(field) => ctx.value(*field),
+            Self::Not(node) => Value::Bool(!node.eval_bool(ctx)),
+            Self::And(lhs, rhs) => Value::Bool(lhs.eval_bool(ctx) && rhs.eval_bool(ctx)),
+            Self::Or(lhs, rhs) => Value::Bool(lhs.eval_bool(ctx) || rhs.eval_bool(ctx)),
+            Self::Compare(cmp, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(ctx), rhs.eval(ctx));
+                Value::Bool(match cmp {
+                    Comparison::Eq => lhs == rhs,
+                    Comparison::Ne => lhs != rhs,
+                    Comparison::Lt => lhs < rhs,
+                    Comparison::Le => lhs <= rhs,
+                    Comparison::Gt => lhs > rhs,
+                    Comparison::Ge => lhs >= rhs,
+                })
+            }
+            Self::Call(function, lhs, rhs) => {
+                let (Value::Str(lhs), Value::Str(rhs)) = (lhs.eval(ctx), rhs.eval(ctx)) else {
+                    return Value::Bool(false);
+                };
+                Value::Bool(match function {
+                    Function::StartsWith => lhs.starts_with(&rhs),
+                    Function::EndsWith => lhs.ends_with(&rhs),
+                    Function::Contains => lhs.contains(&rhs),
+                })
+            }
+        }
+    }
+
+    /// Evaluate boolean node against an event context
+    fn eval_bool(&self, ctx: &dyn Context) -> bool {
+        matches!(self.eval(ctx), Value::Bool(true))
+    }
+}
+
+/// Boolean expression, type checked when parsed
+#[derive(Debug)]
+pub(crate) struct Expr(Node);
+
+impl Expr {
+    /// Evaluate expression against an event context
+    pub(crate) fn eval(&self, ctx: &dyn Context) -> bool {
+        self.0.eval_bool(ctx)
+    }
+}
+
+impl std::str::FromStr for Expr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let (node, type_) = parser.or()?;
+        if let Some(token) = parser.tokens.next() {
+            return Err(Error::UnexpectedToken(token.to_string()));
+        }
+        check_type("when", Type::Bool, type_)?;
+        Ok(Self(node))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Expr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|err| serde::de::Error::custom(format!("Invalid expression {s:?}: {err}")))
+    }
+}
+
+/// Lexical token
+#[derive(Debug, PartialEq)]
+enum Token {
+    /// Field, function, or boolean literal
+    Ident(String),
+    /// Integer literal
+    Int(i64),
+    /// String literal
+    Str(String),
+    /// Operator or punctuation
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(ident) => write!(f, "identifier {ident:?}"),
+            Self::Int(i) => write!(f, "integer {i}"),
+            Self::Str(s) => write!(f, "string {s:?}"),
+            Self::Symbol(symbol) => write!(f, "{symbol:?}"),
+        }
+    }
+}
+
+/// Operators and punctuation, longest first so that a prefix does not shadow them
+const SYMBOLS: [&str; 13] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", ",", "-",
+];
+
+/// Split expression into tokens
+fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(offset, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            tokens.push(Token::Str(string_literal(&mut chars, c, offset)?));
+        } else if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some((_, d)) = chars.next_if(|(_, d)| d.is_ascii_digit() || *d == '_') {
+                if d != '_' {
+                    digits.push(d);
+                }
+            }
+            tokens.push(Token::Int(
+                digits.parse().map_err(|_| Error::InvalidInteger(digits))?,
+            ));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some((_, i)) = chars.next_if(|(_, i)| i.is_ascii_alphanumeric() || *i == '_')
+            {
+                ident.push(i);
+            }
+            tokens.push(Token::Ident(ident));
+        } else {
+            let rest = s.get(offset..).unwrap_or_default();
+            let symbol = SYMBOLS
+                .into_iter()
+                .find(|symbol| rest.starts_with(symbol))
+                .ok_or(Error::UnexpectedChar(c, offset))?;
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Read string literal after its opening quote, handling backslash escapes
+fn string_literal(
+    chars: &mut Peekable<CharIndices>,
+    quote: char,
+    start: usize,
+) -> Result<String, Error> {
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some((_, c)) if c == quote => return Ok(s),
+            Some((_, '\\')) => {
+                let (_, c) = chars.next().ok_or(Error::UnterminatedString(start))?;
+                s.push(c);
+            }
+            Some((_, c)) => s.push(c),
+            None => return Err(Error::UnterminatedString(start)),
+        }
+    }
+}
+
+/// Check the type of an operand
+fn check_type(operator: &'static str, expected: Type, found: Type) -> Result<(), Error> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(Error::TypeMismatch {
+            operator,
+            expected,
+            found,
+        })
+    }
+}
+
+/// Recursive descent parser, building type checked nodes
+struct Parser<I: Iterator<Item = Token>> {
+    /// Remaining tokens
+    tokens: Peekable<I>,
+}
+
+impl<I: Iterator<Item = Token>> Parser<I> {
+    /// Consume the next token if it is the given symbol
+    fn eat(&mut self, symbol: &str) -> bool {
+        self.tokens
+            .next_if(|t| matches!(t, Token::Symbol(s) if *s == symbol))
+            .is_some()
+    }
+
+    /// Consume the next token, which must be the given symbol
+    fn expect(&mut self, symbol: &str) -> Result<(), Error> {
+        match self.tokens.next() {
+            Some(Token::Symbol(s)) if s == symbol => Ok(()),
+            Some(token) => Err(Error::UnexpectedToken(token.to_string())),
+            None => Err(Error::UnexpectedEnd),
+        }
+    }
+
+    /// `or := and ("||" and)*`
+    fn or(&mut self) -> Result<(Node, Type), Error> {
+        let (mut node, type_) = self.and()?;
+        while self.eat("||") {
+            check_type("||", Type::Bool, type_)?;
+            let (rhs, rhs_type) = self.and()?;
+            check_type("||", Type::Bool, rhs_type)?;
+            node = Node::Or(Box::new(node), Box::new(rhs));
+        }
+        Ok((node, type_))
+    }
+
+    /// `and := not ("&&" not)*`
+    fn and(&mut self) -> Result<(Node, Type), Error> {
+        let (mut node, type_) = self.not()?;
+        while self.eat("&&") {
+            check_type("&&", Type::Bool, type_)?;
+            let (rhs, rhs_type) = self.not()?;
+            check_type("&&", Type::Bool, rhs_type)?;
+            node = Node::And(Box::new(node), Box::new(rhs));
+        }
+        Ok((node, type_))
+    }
+
+    /// `not := "!" not | comparison`
+    fn not(&mut self) -> Result<(Node, Type), Error> {
+        if self.eat("!") {
+            let (node, type_) = self.not()?;
+            check_type("!", Type::Bool, type_)?;
+            Ok((Node::Not(Box::new(node)), Type::Bool))
+        } else {
+            self.comparison()
+        }
+    }
+
+    /// `comparison := primary (("==" | "!=" | "<" | "<=" | ">" | ">=") primary)?`
+    fn comparison(&mut self) -> Result<(Node, Type), Error> {
+        let (lhs, lhs_type) = self.primary()?;
+        let Some((symbol, cmp)) = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ]
+        .into_iter()
+        .find(|(symbol, _)| self.eat(symbol)) else {
+            return Ok((lhs, lhs_type));
+        };
+        let (rhs, rhs_type) = self.primary()?;
+        check_type(symbol, lhs_type, rhs_type)?;
+        if !matches!(cmp, Comparison::Eq | Comparison::Ne) {
+            // Ordering strings or booleans is likely a mistake
+            check_type(symbol, Type::Int, lhs_type)?;
+        }
+        Ok((Node::Compare(cmp, Box::new(lhs), Box::new(rhs)), Type::Bool))
+    }
+
+    /// `primary := int | "-" int | string | "true" | "false" | field | function "(" or "," or ")"
+    /// | "(" or ")"`
+    fn primary(&mut self) -> Result<(Node, Type), Error> {
+        match self.tokens.next().ok_or(Error::UnexpectedEnd)? {
+            Token::Int(i) => Ok((Node::Literal(Value::Int(i)), Type::Int)),
+            Token::Symbol("-") => match self.tokens.next() {
+                Some(Token::Int(i)) => Ok((Node::Literal(Value::Int(-i)), Type::Int)),
+                Some(token) => Err(Error::UnexpectedToken(token.to_string())),
+                None => Err(Error::UnexpectedEnd),
+            },
+            Token::Str(s) => Ok((Node::Literal(Value::Str(s)), Type::Str)),
+            Token::Symbol("(") => {
+                let node = self.or()?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Token::Ident(ident) if ident == "true" => {
+                Ok((Node::Literal(Value::Bool(true)), Type::Bool))
+            }
+            Token::Ident(ident) if ident == "false" => {
+                Ok((Node::Literal(Value::Bool(false)), Type::Bool))
+            }
+            Token::Ident(ident) if self.eat("(") => self.call(&ident),
+            Token::Ident(ident) => Field::ALL
+                .into_iter()
+                .find(|(name, _)| *name == ident)
+                .map(|(_, field)| (Node::Field(field), field.type_()))
+                .ok_or(Error::UnknownField(ident)),
+            token @ Token::Symbol(_) => Err(Error::UnexpectedToken(token.to_string())),
+        }
+    }
+
+    /// Arguments of a function call, after its opening parenthesis
+    fn call(&mut self, ident: &str) -> Result<(Node, Type), Error> {
+        let (name, function) = Function::ALL
+            .into_iter()
+            .find(|(name, _)| *name == ident)
+            .ok_or_else(|| Error::UnknownFunction(ident.to_owned()))?;
+        let mut args = Vec::new();
+        if !self.eat(")") {
+            loop {
+                let (arg, type_) = self.or()?;
+                check_type(name, Type::Str, type_)?;
+                args.push(arg);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        let count = args.len();
+        let mut args = args.into_iter();
+        let (Some(lhs), Some(rhs), None) = (args.next(), args.next(), args.next()) else {
+            return Err(Error::ArgumentCount(name, 2, count));
+        };
+        Ok((
+            Node::Call(function, Box::new(lhs), Box::new(rhs)),
+            Type::Bool,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    /// Context of a file event, recording the fields it is asked for
+    #[derive(Default)]
+    struct TestContext {
+        /// Fields asked for, in order
+        asked: RefCell<Vec<Field>>,
+    }
+
+    impl Context for TestContext {
+        fn value(&self, field: Field) -> Value {
+            self.asked.borrow_mut().push(field);
+            match field {
+                Field::Event => Value::Str("file_down_sync_done".to_owned()),
+                Field::Path => Value::Str("docs/report.pdf".to_owned()),
+                Field::Folder => Value::Str("/data/folder".to_owned()),
+                Field::FolderId => Value::Str("fid1".to_owned()),
+                Field::FolderLabel => Value::Str("Folder".to_owned()),
+                Field::Size => Value::Int(150_000_000),
+                Field::Device => Value::Str("SCANNER".to_owned()),
+                Field::Error => Value::Str(String::new()),
+                Field::State => Value::Str("idle".to_owned()),
+            }
+        }
+    }
+
+    /// Evaluate an expression against the test context
+    fn eval(s: &str) -> bool {
+        s.parse::<Expr>().unwrap().eval(&TestContext::default())
+    }
+
+    /// Operators must follow the usual precedence and semantics
+    #[test]
+    fn evaluate_expressions() {
+        assert!(eval("size > 100_000_000"));
+        assert!(eval("size >= 150000000 && size <= 150000000"));
+        assert!(!eval("size < -1"));
+        assert!(eval(r#"device == "SCANNER" || device == 'LAPTOP1'"#));
+        assert!(eval(r#"!(folder_label != "Folder")"#));
+        assert!(eval(
+            r#"ends_with(path, ".pdf") && starts_with(path, "docs/")"#
+        ));
+        assert!(!eval(r#"contains(folder, "other")"#));
+        assert!(eval(
+            r#"event == "folder_down_sync_done" || true && folder_id == "fid1""#
+        ));
+        assert!(!eval(
+            r#"(event == "folder_down_sync_done" || true) && false"#
+        ));
+        assert!(eval(
+            r#"path == "docs/\"report\".pdf" || path == 'docs/report.pdf'"#
+        ));
+        assert!(eval(r#"error == "" && state == "idle""#));
+    }
+
+    /// Fields must only be computed if needed to evaluate the expression
+    #[test]
+    fn lazy_field_evaluation() {
+        let ctx = TestContext::default();
+
+        assert!(
+            !"size < 1000 && device == \"SCANNER\""
+                .parse::<Expr>()
+                .unwrap()
+                .eval(&ctx)
+        );
+
+        assert_eq!(*ctx.asked.borrow(), [Field::Size]);
+    }
+
+    /// Invalid or ill typed expressions must be rejected when parsed
+    #[test]
+    fn reject_invalid_expressions() {
+        for s in [
+            "",
+            "size >",
+            "size > 1 1",
+            "(size > 1",
+            "siz > 1",
+            "path",
+            "size == \"1\"",
+            "path > \"a\"",
+            "!size",
+            "size && true",
+            "lowercase(path)",
+            "ends_with(path)",
+            "ends_with(path, 1)",
+            "path == \"unterminated",
+            "path == \"a\" ; true",
+            "size > 99999999999999999999",
+            "error > 0",
+            "state",
+        ] {
+            assert!(s.parse::<Expr>().is_err(), "{s:?}");
+        }
+    }
+}
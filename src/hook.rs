@@ -1,15 +1,72 @@
 //! Code to run hooks commands
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    io::{self, Write as _},
+    mem,
+    num::NonZeroUsize,
+    os::{
+        fd::OwnedFd,
+        unix::{
+            ffi::OsStrExt as _,
+            fs::{self as unix_fs, OpenOptionsExt as _},
+            process::CommandExt as _,
+        },
+    },
     path::{Path, PathBuf},
-    process::{Child, Command, Stdio},
+    process::{self, Child, Command, ExitStatus, Stdio},
     ptr,
-    sync::{Arc, Weak, mpsc},
-    time::Duration,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use crate::config;
+use jiff::civil;
+use nix::{
+    sys::{
+        signal::{Signal, killpg},
+        stat,
+    },
+    unistd::Pid,
+};
+
+use crate::{config, limits, output, schedule, syncthing};
+
+/// Default maximum duration of a hook condition command
+const DEFAULT_CONDITION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default time to wait for hook processes to exit after terminating them on timeout
+const DEFAULT_TIMEOUT_GRACE: Duration = Duration::from_secs(10);
+
+/// Default delay before the first retry of a failed hook run
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Maximum delay before a retry of a failed hook run, whatever the backoff
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Maximum number of runs waiting for a free slot, beyond which the lowest priority ones are
+/// dropped
+const MAX_WAITING_RUNS: usize = 10_000;
+
+/// Version of the JSON document written to the input of hooks, incremented on incompatible changes
+const STDIN_JSON_VERSION: u32 = 1;
+
+/// Default size beyond which the log file of a hook is rotated
+const DEFAULT_LOG_FILE_MAX_SIZE: bytesize::ByteSize = bytesize::ByteSize::mib(10);
+
+/// Default number of rotated log files kept for a hook
+const DEFAULT_LOG_FILE_COUNT: usize = 5;
+
+/// Shell to run the commands of hooks through, if enabled
+const SHELL: &str = "/bin/sh";
+
+/// Counter to name unique paths files
+static PATHS_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
 
 /// Unique identifier for a folder hook
 #[derive(Eq, Hash, PartialEq)]
@@ -25,143 +82,1666 @@ impl FolderHookId {
     }
 }
 
-/// Run a given hook for a given path/folder
-pub(crate) fn run(
+/// Identifier of the runs of a hook that can not run concurrently
+#[derive(Eq, Hash, PartialEq)]
+struct ConcurrencyId {
+    /// Hook of the runs
+    hook: FolderHookId,
+    /// Concurrency key of the runs, rendered for their event context
+    key: String,
+}
+
+impl ConcurrencyId {
+    /// Identifier of the run of a hook for an event context
+    fn new(hook: &config::FolderHook, ctx: &Context) -> Self {
+        let key = match &hook.concurrency_key {
+            config::ConcurrencyKey::Hook => String::new(),
+            config::ConcurrencyKey::Path => {
+                let path = ctx.path.as_ref().map(|p| ctx.folder.join(p));
+                path.as_ref()
+                    .unwrap_or(&ctx.folder)
+                    .to_string_lossy()
+                    .into_owned()
+            }
+            config::ConcurrencyKey::Folder => ctx.folder.to_string_lossy().into_owned(),
+            config::ConcurrencyKey::Template(template) => template.render(ctx),
+        };
+        Self {
+            hook: FolderHookId::from_hook(hook),
+            key,
+        }
+    }
+}
+
+/// Context of an event a hook runs for
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Context {
+    /// Event kind
+    pub event: config::FolderEvent,
+    /// Path of the file the event is about, relative to the folder
+    pub path: Option<PathBuf>,
+    /// Local path of the folder
+    pub folder: PathBuf,
+    /// Syncthing id of the folder
+    pub folder_id: String,
+    /// Syncthing label of the folder
+    pub folder_label: String,
+    /// Server event the event comes from, if any
+    pub source: Option<syncthing::ServerEvent>,
+    /// Short id of the device that made the synced change of the event file, if synced down
+    pub device_id: Option<String>,
+    /// Name of the device that made the synced change of the event file, if configured
+    pub device_name: Option<String>,
+    /// Information of the event file, if the hook enriches its runs with it
+    pub file: Option<syncthing::FileInfo>,
+    /// Subdirectory the hook is scoped to, relative to the folder, empty for the whole folder
+    pub subdir: PathBuf,
+    /// Named groups captured by the regex filter of the hook, as `(name, value)` pairs
+    pub captures: Vec<(String, String)>,
+    /// Paths of the files of the events collected by debouncing, relative to the folder
+    pub paths: Vec<PathBuf>,
+}
+
+impl Context {
+    /// Path of the file the event is about, relative to the subdirectory the hook is scoped to
+    fn subdir_path(&self) -> Option<&Path> {
+        self.path
+            .as_deref()
+            .map(|p| p.strip_prefix(&self.subdir).unwrap_or(p))
+    }
+
+    /// Absolute path of the file the event is about
+    pub(crate) fn abs_path(&self) -> Option<PathBuf> {
+        self.path.as_ref().map(|p| self.folder.join(p))
+    }
+}
+
+/// Check the file conditions of a hook against the file an event is about, returning the reason
+/// of the first unmet one, if any
+fn unmet_file_condition(hook: &config::FolderHook, ctx: &Context) -> Option<String> {
+    let path = ctx.path.as_ref()?;
+    if hook.min_size.is_none()
+        && hook.max_size.is_none()
+        && hook.max_age.is_none()
+        && hook.mime.is_empty()
+    {
+        return None;
+    }
+
+    let path = ctx.folder.join(path);
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(err) => return Some(format!("Unable to get metadata of {path:?}: {err}")),
+    };
+    let size = bytesize::ByteSize::b(metadata.len());
+    if hook.min_size.is_some_and(|min_size| size < min_size) {
+        return Some(format!("File size {size} is below minimum"));
+    }
+    if hook.max_size.is_some_and(|max_size| size > max_size) {
+        return Some(format!("File size {size} is above maximum"));
+    }
+    if let Some(max_age) = hook.max_age {
+        // A modification time in the future is considered as a null age
+        let age = match metadata.modified() {
+            Ok(mtime) => mtime.elapsed().unwrap_or_default(),
+            Err(err) => {
+                return Some(format!(
+                    "Unable to get modification time of {path:?}: {err}"
+                ));
+            }
+        };
+        if age > max_age {
+            return Some(format!("File age {age:?} is above maximum"));
+        }
+    }
+    if !hook.mime.is_empty() {
+        let mime = match infer::get_from_path(&path) {
+            Ok(kind) => kind.map(|k| k.mime_type()),
+            Err(err) => return Some(format!("Unable to read {path:?}: {err}")),
+        };
+        let is_allowed = |file_mime: &str| {
+            hook.mime.iter().any(|allowed| {
+                allowed
+                    .strip_suffix("/*")
+                    .map_or(allowed == file_mime, |type_| {
+                        file_mime.split_once('/').is_some_and(|(t, _)| t == type_)
+                    })
+            })
+        };
+        if !mime.is_some_and(is_allowed) {
+            return Some(format!("File MIME type {mime:?} is not allowed"));
+        }
+    }
+    None
+}
+
+/// Build a command of a hook with the environment of an event context, for the given run attempt
+fn command(
+    cmdline: &config::CommandLine,
     hook: &config::FolderHook,
-    path: Option<&Path>,
-    folder: &Path,
-    reaper_tx: &mpsc::Sender<RunningHook>,
-    running_hooks: &mut HashMap<FolderHookId, Weak<()>>,
+    ctx: &Context,
+    attempt: u32,
+) -> Command {
+    let source = ctx.source.as_ref();
+    let mut command = if hook.shell.unwrap_or(false) {
+        let mut shell = Command::new(SHELL);
+        shell.arg("-c").arg(cmdline.script.render_quoted(ctx));
+        shell
+    } else {
+        let mut direct = Command::new(cmdline.args[0].render_os(ctx));
+        direct.args(cmdline.args[1..].iter().map(|a| a.render_os(ctx)));
+        direct
+    };
+    match &hook.cwd {
+        Some(config::WorkingDir::Folder) => {
+            command.current_dir(&ctx.folder);
+        }
+        Some(config::WorkingDir::Path(path)) => {
+            command.current_dir(ctx.folder.join(path));
+        }
+        None => {}
+    }
+    let credentials = hook.credentials.clone();
+    let umask = hook.umask;
+    let limits = limits::Limits::new(hook);
+    if credentials.is_some() || umask.is_some() || limits.is_some() {
+        // SAFETY: the closure only makes async-signal-safe calls, without allocating
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(umask) = umask {
+                    stat::umask(umask);
+                }
+                // Before switching user, which may not be allowed to raise priorities
+                if let Some(limits) = &limits {
+                    limits.apply()?;
+                }
+                if let Some(credentials) = &credentials {
+                    credentials.apply()?;
+                }
+                Ok(())
+            });
+        }
+    }
+    if hook.clear_env.unwrap_or(false) {
+        command.env_clear().envs(
+            hook.keep_env
+                .iter()
+                .filter_map(|name| env::var_os(name).map(|val| (name, val))),
+        );
+    }
+    command
+        .envs(&hook.env)
+        .env("STFED_HOOK_NAME", hook.name().as_ref())
+        .env("STFED_ATTEMPT", attempt.to_string())
+        .env("STFED_EVENT", ctx.event.name())
+        .env(
+            "STFED_EVENT_ID",
+            source.map(|s| s.id.to_string()).unwrap_or_default(),
+        )
+        .env("STFED_EVENT_TIME", source.map_or("", |s| s.time.as_str()))
+        .env("STFED_PATH", ctx.path.as_deref().unwrap_or(Path::new("")))
+        .env("STFED_ABS_PATH", ctx.abs_path().unwrap_or_default())
+        .env("STFED_FOLDER", &ctx.folder)
+        .env("STFED_FOLDER_ID", &ctx.folder_id)
+        .env("STFED_FOLDER_LABEL", &ctx.folder_label)
+        .env(
+            "STFED_DEVICE_ID",
+            ctx.device_id.as_deref().unwrap_or_default(),
+        )
+        .env(
+            "STFED_DEVICE_NAME",
+            ctx.device_name.as_deref().unwrap_or_default(),
+        )
+        .env(
+            "STFED_SUBDIR_PATH",
+            ctx.subdir_path().unwrap_or(Path::new("")),
+        )
+        .envs(
+            ctx.captures
+                .iter()
+                .map(|(name, val)| (format!("STFED_MATCH_{}", name.to_ascii_uppercase()), val)),
+        )
+        .stdin(Stdio::null());
+    if let Some(file) = &ctx.file {
+        command
+            .env("STFED_FILE_SIZE", file.size.to_string())
+            .env("STFED_FILE_MODIFIED", &file.modified)
+            .env("STFED_FILE_PERMISSIONS", &file.permissions)
+            .env("STFED_FILE_VERSION", file.version.join(","))
+            .env("STFED_FILE_MODIFIED_BY", &file.modified_by)
+            .env(
+                "STFED_FILE_BLOCKS_HASH",
+                file.blocks_hash.as_deref().unwrap_or_default(),
+            );
+    }
+    command
+}
+
+/// JSON document describing an event context, for a run attempt of a hook
+fn stdin_document(hook: &config::FolderHook, ctx: &Context, attempt: u32) -> serde_json::Value {
+    let lossy = |p: &Path| p.to_string_lossy().into_owned();
+    let captures: BTreeMap<_, _> = ctx
+        .captures
+        .iter()
+        .map(|(name, val)| (name.as_str(), val.as_str()))
+        .collect();
+    serde_json::json!({
+        "version": STDIN_JSON_VERSION,
+        "hook": hook.name(),
+        "event": ctx.event,
+        "folder": {
+            "id": ctx.folder_id,
+            "label": ctx.folder_label,
+            "path": lossy(&ctx.folder),
+        },
+        "path": ctx.path.as_deref().map(lossy),
+        "abs_path": ctx.abs_path().as_deref().map(lossy),
+        "subdir_path": ctx.subdir_path().map(lossy),
+        "captures": captures,
+        "paths": ctx.paths.iter().map(|p| lossy(p)).collect::<Vec<_>>(),
+        "attempt": attempt,
+        "device": ctx.device_id.as_ref().map(|id| serde_json::json!({
+            "id": id,
+            "name": ctx.device_name,
+        })),
+        "file": ctx.file,
+        "syncthing_event": ctx.source.as_ref().map(|s| serde_json::json!({
+            "id": s.id,
+            "globalID": s.global_id,
+            "type": s.event_type,
+            "time": s.time,
+            "data": s.data,
+        })),
+    })
+}
+
+/// Delay before running again a hook run attempt that exited with `status`, or `None` if it
+/// must not be retried
+fn retry_delay(policy: &config::RetryPolicy, attempt: u32, status: ExitStatus) -> Option<Duration> {
+    let retryable = if policy.exit_codes.is_empty() {
+        !status.success()
+    } else {
+        status
+            .code()
+            .is_some_and(|c| policy.exit_codes.contains(&c))
+    };
+    if !retryable || (attempt >= policy.attempts) {
+        return None;
+    }
+    let backoff = policy.backoff.unwrap_or(DEFAULT_RETRY_BACKOFF);
+    let delay = 2_u32
+        .checked_pow(attempt - 1)
+        .map_or(Duration::MAX, |factor| backoff.saturating_mul(factor));
+    // Unbounded delays would overflow when added to the current instant
+    Some(
+        policy
+            .max_backoff
+            .map_or(delay, |max| delay.min(max))
+            .min(MAX_RETRY_BACKOFF),
+    )
+}
+
+/// Request to run a hook for an event, sent to the reaper thread
+pub(crate) struct Request<'a> {
+    /// Hook to run
+    hook: &'a config::FolderHook,
+    /// Event context to run it with
+    ctx: Context,
+    /// Attempt number of the run, from 1
+    attempt: u32,
+}
+
+/// Request to run a given hook for a given event context
+pub(crate) fn run<'a>(
+    hook: &'a config::FolderHook,
+    ctx: Context,
+    reaper_tx: &mpsc::Sender<Request<'a>>,
 ) -> anyhow::Result<()> {
-    let allow_concurrent = hook.allow_concurrent.unwrap_or(false);
-    let hook_id = FolderHookId::from_hook(hook);
-    let already_running = running_hooks
-        .get(&hook_id)
-        .and_then(Weak::upgrade)
-        .is_some();
-    if allow_concurrent || !already_running {
-        log::info!("Running hook: {hook:?} with path {path:?} and folder {folder:?}");
-
-        let Ok(child) = Command::new(&hook.command[0])
-            .args(&hook.command[1..])
-            .env("STFED_PATH", path.unwrap_or(&PathBuf::from("")))
-            .env("STFED_FOLDER", folder)
-            .stdin(Stdio::null())
-            .spawn()
-            .inspect_err(|err| {
-                log::error!(
-                    "Failed to spawn hook command {command:?}: {err}",
-                    command = hook.command
+    reaper_tx
+        .send(Request {
+            hook,
+            ctx,
+            attempt: 1,
+        })
+        .map_err(|_| anyhow::anyhow!("Reaper thread is gone"))
+}
+
+/// Timeout enforcement state of a hook process
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TimeoutState {
+    /// Timeout not expired, or no timeout
+    Running,
+    /// Process group sent SIGTERM at the given time
+    Terminated(Instant),
+    /// Process group sent SIGKILL
+    Killed,
+}
+
+/// A spawned hook process, whose liveness marks its hook as running
+pub(crate) struct RunningHook<'a> {
+    /// Hook the process runs
+    hook: &'a config::FolderHook,
+    /// Event context the hook runs with
+    ctx: Context,
+    /// Attempt number of the run, from 1
+    attempt: u32,
+    /// File of the paths of the debounced events, removed when the process is reaped
+    _paths_file: Option<PathsFile>,
+    /// Token whose drop unmarks the hook as running
+    _token: Arc<()>,
+    /// The spawned process
+    child: Child,
+    /// Time the process was spawned at
+    start: Instant,
+    /// Timeout enforcement state
+    timeout_state: TimeoutState,
+}
+
+impl RunningHook<'_> {
+    /// Terminate the process group of the hook if its timeout expired, or kill it if it is
+    /// still running after the grace period
+    fn enforce_timeout(&mut self) {
+        let Some(timeout) = self.hook.timeout else {
+            return;
+        };
+        let grace = self.hook.timeout_grace.unwrap_or(DEFAULT_TIMEOUT_GRACE);
+        let signal = match self.timeout_state {
+            TimeoutState::Running if self.start.elapsed() >= timeout => {
+                log::warn!(
+                    "Hook {:?} timed out after {timeout:?}, terminating it",
+                    self.hook.command
                 );
-            })
-        else {
+                self.timeout_state = TimeoutState::Terminated(Instant::now());
+                Signal::SIGTERM
+            }
+            TimeoutState::Terminated(terminated) if terminated.elapsed() >= grace => {
+                log::warn!(
+                    "Hook {:?} still running {grace:?} after being terminated, killing it",
+                    self.hook.command
+                );
+                self.timeout_state = TimeoutState::Killed;
+                Signal::SIGKILL
+            }
+            TimeoutState::Running | TimeoutState::Terminated(_) | TimeoutState::Killed => return,
+        };
+        // Hooks with a timeout lead their own process group, see `Reaper::spawn`
+        let Ok(pgid) = i32::try_from(self.child.id()) else {
+            return;
+        };
+        if let Err(err) = killpg(Pid::from_raw(pgid), signal) {
+            log::error!("Failed to send {signal} to hook process group {pgid}: {err}");
+        }
+    }
+}
+
+/// A spawned hook condition process, whose liveness marks its hook as running
+struct RunningCondition<'a> {
+    /// Hook the condition is of
+    hook: &'a config::FolderHook,
+    /// Event context the hook runs with
+    ctx: Context,
+    /// Attempt number of the run, from 1
+    attempt: u32,
+    /// Token whose drop unmarks the hook as running
+    _token: Arc<()>,
+    /// The spawned process
+    child: Child,
+    /// Time the process was spawned at
+    start: Instant,
+}
+
+/// Add the earlier paths of debounced events missing from context `into`, keeping them in event
+/// order
+fn merge_paths(into: &mut Context, mut paths: Vec<PathBuf>) {
+    paths.retain(|p| !into.paths.contains(p));
+    paths.append(&mut into.paths);
+    into.paths = paths;
+}
+
+/// Temporary file of the paths of debounced events, removed when dropped
+struct PathsFile(PathBuf);
+
+impl PathsFile {
+    /// Write the paths of an event context to a new temporary file, only readable by its owner,
+    /// the user the hook runs as
+    fn new(ctx: &Context, hook: &config::FolderHook) -> io::Result<Self> {
+        let separator = hook.paths_separator.unwrap_or_default();
+        let filepath = env::temp_dir().join(format!(
+            "stfed-paths-{}-{}",
+            process::id(),
+            PATHS_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut data = Vec::new();
+        for path in &ctx.paths {
+            data.extend_from_slice(path.as_os_str().as_bytes());
+            data.push(separator.byte());
+        }
+        // Never follow a link planted at the predictable path
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&filepath)?;
+        let paths_file = Self(filepath);
+        if let Some(credentials) = &hook.credentials {
+            unix_fs::fchown(&file, Some(credentials.uid().as_raw()), None)?;
+        }
+        file.write_all(&data)?;
+        Ok(paths_file)
+    }
+}
+
+impl Drop for PathsFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Events collected for a debounced hook, until their folder is quiet
+struct Debounced<'a> {
+    /// Hook to run
+    hook: &'a config::FolderHook,
+    /// Context of the latest event, with the paths of all collected events
+    ctx: Context,
+    /// Time of the latest event
+    last_event: Instant,
+}
+
+impl Debounced<'_> {
+    /// Time the hook can run at, if no other event comes
+    fn due(&self) -> Instant {
+        self.last_event + self.hook.debounce.unwrap_or_default()
+    }
+}
+
+/// Run of a failed hook to attempt again
+struct PendingRetry<'a> {
+    /// Time to retry at
+    due: Instant,
+    /// Hook to run
+    hook: &'a config::FolderHook,
+    /// Event context to run it with
+    ctx: Context,
+    /// Attempt number of the retry
+    attempt: u32,
+}
+
+/// Run waiting for a free slot to start
+struct WaitingRun<'a> {
+    /// Hook to run
+    hook: &'a config::FolderHook,
+    /// Event context to run it with
+    ctx: Context,
+    /// Attempt number of the run
+    attempt: u32,
+}
+
+/// Deferred run, as persisted
+#[derive(serde::Deserialize, serde::Serialize)]
+struct PersistedRun {
+    /// Index of the hook in the hooks configuration
+    hook: usize,
+    /// Command of the hook, as configured, to detect hooks configuration changes
+    command: Vec<String>,
+    /// Event context to run the hook with
+    ctx: Context,
+}
+
+/// State of hook runs, owned by the reaper thread
+pub(crate) struct Reaper<'a> {
+    /// All configured hooks, to identify them in the persisted deferred runs
+    hooks: &'a [config::FolderHook],
+    /// Liveness of the last process of each hook and concurrency key
+    running_hooks: HashMap<ConcurrencyId, Weak<()>>,
+    /// Processes to wait for
+    watched: Vec<RunningHook<'a>>,
+    /// Condition processes to wait for, before running their hook
+    conditions: Vec<RunningCondition<'a>>,
+    /// Runs deferred until the schedule of their hook allows them
+    deferred: Vec<Request<'a>>,
+    /// Failed runs to attempt again
+    retries: Vec<PendingRetry<'a>>,
+    /// Events collected for debounced hooks
+    debounced: Vec<Debounced<'a>>,
+    /// Runs waiting for their hook to exit, in event order
+    queued: Vec<Request<'a>>,
+    /// Maximum number of hook processes running at the same time
+    max_running: Option<NonZeroUsize>,
+    /// Runs waiting for a free slot, by decreasing priority then in event order
+    waiting: Vec<WaitingRun<'a>>,
+    /// Log files of the hooks, by path, shared by their runs
+    log_files: HashMap<PathBuf, Arc<Mutex<output::LogFile>>>,
+    /// File the deferred runs are persisted to, if any
+    deferred_filepath: Option<PathBuf>,
+    /// Whether the deferred runs changed since they were last persisted
+    deferred_changed: bool,
+}
+
+impl<'a> Reaper<'a> {
+    /// Create state, loading the deferred runs persisted in `deferred_filepath`, if any, and
+    /// running at most `max_running` hook processes at the same time
+    pub(crate) fn new(
+        hooks: &'a [config::FolderHook],
+        deferred_filepath: Option<PathBuf>,
+        max_running: Option<NonZeroUsize>,
+    ) -> Self {
+        let mut reaper = Self {
+            hooks,
+            running_hooks: HashMap::new(),
+            watched: Vec::new(),
+            conditions: Vec::new(),
+            deferred: Vec::new(),
+            retries: Vec::new(),
+            debounced: Vec::new(),
+            queued: Vec::new(),
+            max_running,
+            waiting: Vec::new(),
+            log_files: HashMap::new(),
+            deferred_filepath,
+            deferred_changed: false,
+        };
+        if let Err(err) = reaper.load_deferred() {
+            log::error!("Failed to load deferred hook runs: {err:#}");
+        }
+        reaper
+    }
+
+    /// Load the persisted deferred runs, skipping those of hooks no longer configured
+    fn load_deferred(&mut self) -> anyhow::Result<()> {
+        let Some(filepath) = &self.deferred_filepath else {
             return Ok(());
         };
+        let data = match fs::read_to_string(filepath) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let runs: Vec<PersistedRun> = serde_json::from_str(&data)?;
+        for run in runs {
+            match self.hooks.get(run.hook).filter(|h| {
+                h.command
+                    .args
+                    .iter()
+                    .map(ToString::to_string)
+                    .eq(run.command.iter().cloned())
+            }) {
+                Some(hook) => self.deferred.push(Request {
+                    hook,
+                    ctx: run.ctx,
+                    attempt: 1,
+                }),
+                None => log::warn!(
+                    "Dropping deferred run of hook {:?} that is no longer configured",
+                    run.command
+                ),
+            }
+        }
+        log::info!("Loaded {} deferred hook run(s)", self.deferred.len());
+        Ok(())
+    }
+
+    /// Persist the deferred runs, if they changed since they were last persisted
+    fn save_deferred(&mut self) {
+        if !mem::take(&mut self.deferred_changed) {
+            return;
+        }
+        let Some(filepath) = &self.deferred_filepath else {
+            return;
+        };
+        let runs: Vec<PersistedRun> = self
+            .deferred
+            .iter()
+            .filter_map(|req| {
+                let index = self.hooks.iter().position(|h| ptr::eq(h, req.hook))?;
+                Some(PersistedRun {
+                    hook: index,
+                    command: req
+                        .hook
+                        .command
+                        .args
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect(),
+                    ctx: req.ctx.clone(),
+                })
+            })
+            .collect();
+        // Write then rename, to never leave a truncated file
+        let tmp_filepath = filepath.with_extension("tmp");
+        if let Err(err) = serde_json::to_string(&runs)
+            .map_err(io::Error::from)
+            .and_then(|data| fs::write(&tmp_filepath, data))
+            .and_then(|()| fs::rename(&tmp_filepath, filepath))
+        {
+            log::error!("Failed to persist deferred hook runs to {filepath:?}: {err}");
+        }
+    }
+
+    /// Start a hook run for an event context, collect the event if the hook is debounced, or
+    /// defer it if outside of the hook schedule at local time `now`
+    fn start(&mut self, hook: &'a config::FolderHook, ctx: Context, now: civil::DateTime) {
+        if hook.debounce.is_some() {
+            self.debounce(hook, ctx);
+        } else {
+            self.run_or_defer(hook, ctx, now);
+        }
+    }
+
+    /// Collect an event of a debounced hook, with the other events of its folder
+    fn debounce(&mut self, hook: &'a config::FolderHook, mut ctx: Context) {
+        // File conditions are about the event file, so they can only be checked now
+        if let Some(reason) = unmet_file_condition(hook, &ctx) {
+            log::debug!("Skipping hook {hook:?} with {ctx:?}: {reason}");
+            return;
+        }
+        let same_folder = self
+            .debounced
+            .iter_mut()
+            .find(|d| ptr::eq(d.hook, hook) && (d.ctx.folder == ctx.folder));
+        if let Some(debounced) = same_folder {
+            ctx.paths = mem::take(&mut debounced.ctx.paths);
+            if let Some(path) = ctx.path.as_ref().filter(|p| !ctx.paths.contains(p)) {
+                ctx.paths.push(path.clone());
+            }
+            debounced.ctx = ctx;
+            debounced.last_event = Instant::now();
+        } else {
+            ctx.paths = ctx.path.iter().cloned().collect();
+            self.debounced.push(Debounced {
+                hook,
+                ctx,
+                last_event: Instant::now(),
+            });
+        }
+    }
+
+    /// Start the runs of debounced hooks whose folder has been quiet long enough
+    ///
+    /// Events of busy hooks are kept collected until they exit, to not lose them whatever the
+    /// `on_busy` policy.
+    fn flush_debounced(&mut self, now: civil::DateTime) {
+        let instant_now = Instant::now();
+        let (due, pending): (Vec<_>, Vec<_>) = mem::take(&mut self.debounced)
+            .into_iter()
+            .partition(|d| (d.due() <= instant_now) && !self.is_busy(d.hook, &d.ctx));
+        self.debounced = pending;
+        for debounced in due {
+            log::info!(
+                "Running debounced hook {:?} for {} event file(s)",
+                debounced.hook.command,
+                debounced.ctx.paths.len()
+            );
+            self.run_or_defer(debounced.hook, debounced.ctx, now);
+        }
+    }
+
+    /// Start a hook run for an event context, or defer it if outside of the hook schedule at
+    /// local time `now`
+    fn run_or_defer(&mut self, hook: &'a config::FolderHook, ctx: Context, now: civil::DateTime) {
+        if schedule::is_open(&hook.schedule, now) {
+            self.spawn(hook, ctx, 1);
+        } else {
+            self.defer(Request {
+                hook,
+                ctx,
+                attempt: 1,
+            });
+        }
+    }
+
+    /// Defer a run, replacing any deferred run of the same hook for the same file, or merging
+    /// it with any deferred run of the same hook for the same folder if debounced
+    fn defer(&mut self, mut req: Request<'a>) {
+        log::info!(
+            "Deferring hook {:?} with {:?} until its schedule allows it",
+            req.hook,
+            req.ctx
+        );
+        let debounced = req.hook.debounce.is_some();
+        let same_run = self.deferred.iter_mut().find(|d| {
+            ptr::eq(d.hook, req.hook)
+                && (d.ctx.folder == req.ctx.folder)
+                && (debounced || (d.ctx.path == req.ctx.path))
+        });
+        match same_run {
+            Some(same_run) => {
+                merge_paths(&mut req.ctx, mem::take(&mut same_run.ctx.paths));
+                *same_run = req;
+            }
+            None => self.deferred.push(req),
+        }
+        self.deferred_changed = true;
+    }
+
+    /// Start the deferred runs whose hook schedule allows them at local time `now`
+    fn replay_deferred(&mut self, now: civil::DateTime) {
+        let (due, deferred): (Vec<_>, Vec<_>) = self
+            .deferred
+            .drain(..)
+            .partition(|req| schedule::is_open(&req.hook.schedule, now));
+        self.deferred = deferred;
+        if due.is_empty() {
+            return;
+        }
+        self.deferred_changed = true;
+        for req in due {
+            log::info!("Replaying deferred run of hook {:?}", req.hook);
+            self.spawn(req.hook, req.ctx, req.attempt);
+        }
+    }
+
+    /// Start the due retries of hooks that are not busy
+    fn start_due_retries(&mut self) {
+        let now = Instant::now();
+        let mut i = 0;
+        while let Some(pending) = self.retries.get(i) {
+            if (pending.due <= now) && !self.is_busy(pending.hook, &pending.ctx) {
+                let retry = self.retries.swap_remove(i);
+                log::info!(
+                    "Retrying hook {:?}, attempt {}",
+                    retry.hook.command,
+                    retry.attempt
+                );
+                self.spawn(retry.hook, retry.ctx, retry.attempt);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Queue a run of a busy hook, or drop it, depending on its `on_busy` policy
+    fn queue(&mut self, mut req: Request<'a>) {
+        match req.hook.on_busy.unwrap_or_default() {
+            config::OnBusy::Skip => {
+                log::warn!(
+                    "A process is already running for hook {:?}, and on_busy = \"skip\", ignoring run with {:?}",
+                    req.hook.command,
+                    req.ctx
+                );
+            }
+            config::OnBusy::QueueOne => {
+                log::info!(
+                    "A process is already running for hook {:?}, queuing a run with {:?}",
+                    req.hook.command,
+                    req.ctx
+                );
+                let id = ConcurrencyId::new(req.hook, &req.ctx);
+                match self
+                    .queued
+                    .iter_mut()
+                    .find(|q| ConcurrencyId::new(q.hook, &q.ctx) == id)
+                {
+                    Some(queued) => {
+                        merge_paths(&mut req.ctx, mem::take(&mut queued.ctx.paths));
+                        *queued = req;
+                    }
+                    None => self.queued.push(req),
+                }
+            }
+            config::OnBusy::QueueAll => {
+                log::info!(
+                    "A process is already running for hook {:?}, queuing a run with {:?}",
+                    req.hook.command,
+                    req.ctx
+                );
+                self.queued.push(req);
+            }
+        }
+    }
+
+    /// Add a run to the runs waiting for a free slot, after those of higher or equal priority
+    fn wait_for_slot(&mut self, run: WaitingRun<'a>) {
+        let index = self
+            .waiting
+            .iter()
+            .position(|w| w.hook.priority < run.hook.priority)
+            .unwrap_or(self.waiting.len());
+        self.waiting.insert(index, run);
+        if self.waiting.len() > MAX_WAITING_RUNS {
+            if let Some(dropped) = self.waiting.pop() {
+                log::warn!(
+                    "Too many hook runs waiting, dropping run of hook {:?} with {:?}",
+                    dropped.hook.command,
+                    dropped.ctx
+                );
+            }
+        }
+        log::info!(
+            "Maximum number of running hooks reached, {} run(s) waiting",
+            self.waiting.len()
+        );
+    }
+
+    /// Start the runs waiting for a slot, as long as slots are free, by priority
+    fn start_waiting(&mut self) {
+        while !self.waiting.is_empty()
+            && self
+                .max_running
+                .is_none_or(|max| self.running_count() < max.get())
+        {
+            let run = self.waiting.remove(0);
+            log::info!(
+                "Starting waiting run of hook {:?}, {} run(s) still waiting",
+                run.hook.command,
+                self.waiting.len()
+            );
+            self.spawn(run.hook, run.ctx, run.attempt);
+        }
+    }
+
+    /// Start the queued runs of hooks that are no longer busy, in event order
+    fn start_queued(&mut self) {
+        let mut i = 0;
+        while let Some(queued) = self.queued.get(i) {
+            if self.is_busy(queued.hook, &queued.ctx) {
+                i += 1;
+            } else {
+                let req = self.queued.remove(i);
+                self.spawn(req.hook, req.ctx, req.attempt);
+            }
+        }
+    }
+
+    /// Whether a hook can not run now for an event context, because it is already running with
+    /// the same concurrency key and does not allow concurrent runs
+    fn is_busy(&self, hook: &config::FolderHook, ctx: &Context) -> bool {
+        !hook.allow_concurrent.unwrap_or(false)
+            && self
+                .running_hooks
+                .get(&ConcurrencyId::new(hook, ctx))
+                .and_then(Weak::upgrade)
+                .is_some()
+    }
+
+    /// Log the output of a hook process, and append it to the log file of the hook, if any
+    fn capture_output(&mut self, hook: &config::FolderHook, child: &mut Child) {
+        let log_file = hook.log_file.as_ref().map(|path| {
+            Arc::clone(self.log_files.entry(path.clone()).or_insert_with(|| {
+                Arc::new(Mutex::new(output::LogFile::new(
+                    path.clone(),
+                    hook.log_file_max_size
+                        .unwrap_or(DEFAULT_LOG_FILE_MAX_SIZE)
+                        .as_u64(),
+                    hook.log_file_count.unwrap_or(DEFAULT_LOG_FILE_COUNT),
+                )))
+            }))
+        });
+        let outputs: Vec<_> = [
+            child
+                .stdout
+                .take()
+                .map(|o| (output::Stream::Stdout, OwnedFd::from(o))),
+            child
+                .stderr
+                .take()
+                .map(|o| (output::Stream::Stderr, OwnedFd::from(o))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !outputs.is_empty() {
+            output::capture(outputs, hook.name().into_owned(), child.id(), log_file);
+        }
+    }
+
+    /// Number of running hook and condition processes, that take a slot
+    fn running_count(&self) -> usize {
+        self.watched.len() + self.conditions.len()
+    }
+
+    /// Spawn a hook process for an event context, or its condition process first, if its
+    /// conditions are met
+    fn spawn(&mut self, hook: &'a config::FolderHook, ctx: Context, attempt: u32) {
+        // File conditions of debounced hooks are checked for each collected event
+        if hook.debounce.is_none() {
+            if let Some(reason) = unmet_file_condition(hook, &ctx) {
+                log::debug!("Skipping hook {hook:?} with {ctx:?}: {reason}");
+                return;
+            }
+        }
+
+        if self.is_busy(hook, &ctx) {
+            self.queue(Request { hook, ctx, attempt });
+            return;
+        }
+
+        if self
+            .max_running
+            .is_some_and(|max| self.running_count() >= max.get())
+        {
+            self.wait_for_slot(WaitingRun { hook, ctx, attempt });
+            return;
+        }
+
+        match &hook.condition {
+            Some(condition) => self.spawn_condition(condition, hook, ctx, attempt),
+            None => self.spawn_process(hook, ctx, attempt),
+        }
+    }
+
+    /// Spawn the condition process of a hook, watched to run the hook if it succeeds in time
+    fn spawn_condition(
+        &mut self,
+        condition: &config::CommandLine,
+        hook: &'a config::FolderHook,
+        ctx: Context,
+        attempt: u32,
+    ) {
+        let child = match command(condition, hook, &ctx, attempt)
+            .stdout(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) => {
+                log::error!("Failed to spawn hook condition command {condition:?}: {err}");
+                return;
+            }
+        };
+        let token = Arc::new(());
+        self.running_hooks
+            .insert(ConcurrencyId::new(hook, &ctx), Arc::downgrade(&token));
+        self.conditions.push(RunningCondition {
+            hook,
+            ctx,
+            attempt,
+            _token: token,
+            child,
+            start: Instant::now(),
+        });
+    }
+
+    /// Spawn a hook process for an event context, whose conditions are met
+    fn spawn_process(&mut self, hook: &'a config::FolderHook, ctx: Context, attempt: u32) {
+        log::info!("Running hook: {hook:?} with {ctx:?}");
+
+        let mut command = command(&hook.command, hook, &ctx, attempt);
+        let paths_file = if hook.debounce.is_some() {
+            match PathsFile::new(&ctx, hook) {
+                Ok(paths_file) => {
+                    command.env("STFED_PATHS_FILE", &paths_file.0);
+                    Some(paths_file)
+                }
+                Err(err) => {
+                    log::error!("Failed to write paths file of hook {hook:?}: {err}");
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        if hook.timeout.is_some() {
+            // Signal the processes spawned by the hook too on timeout
+            command.process_group(0);
+        }
+        let stdin_data = (hook.stdin.unwrap_or_default() == config::Stdin::Json)
+            .then(|| stdin_document(hook, &ctx, attempt).to_string());
+        if stdin_data.is_some() {
+            command.stdin(Stdio::piped());
+        }
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let Ok(mut child) = command.spawn().inspect_err(|err| {
+            log::error!(
+                "Failed to spawn hook command {command:?}: {err}",
+                command = hook.command
+            );
+        }) else {
+            return;
+        };
+        self.capture_output(hook, &mut child);
+
+        if let Some((mut stdin, data)) = child.stdin.take().zip(stdin_data) {
+            // Write from another thread, to not block on a command that does not read its input
+            thread::spawn(move || {
+                if let Err(err) = stdin.write_all(data.as_bytes()) {
+                    if err.kind() != io::ErrorKind::BrokenPipe {
+                        log::warn!("Failed to write hook command input: {err}");
+                    }
+                }
+            });
+        }
+
+        let token = Arc::new(());
+        self.running_hooks
+            .insert(ConcurrencyId::new(hook, &ctx), Arc::downgrade(&token));
+        self.watched.push(RunningHook {
+            hook,
+            ctx,
+            attempt,
+            _token: token,
+            _paths_file: paths_file,
+            child,
+            start: Instant::now(),
+            timeout_state: TimeoutState::Running,
+        });
+    }
+
+    /// Schedule a retry of a hook run attempt that exited with `status`, if its policy allows it
+    fn schedule_retry(
+        &mut self,
+        hook: &'a config::FolderHook,
+        ctx: Context,
+        attempt: u32,
+        status: ExitStatus,
+    ) {
+        let Some(policy) = &hook.retry else {
+            return;
+        };
+        match retry_delay(policy, attempt, status) {
+            Some(delay) => {
+                log::info!(
+                    "Hook {:?} failed with {status}, will retry in {delay:?}",
+                    hook.command
+                );
+                self.retries.push(PendingRetry {
+                    due: Instant::now()
+                        .checked_add(delay)
+                        .unwrap_or_else(|| Instant::now() + MAX_RETRY_BACKOFF),
+                    hook,
+                    ctx,
+                    attempt: attempt + 1,
+                });
+            }
+            None if !status.success() => {
+                log::warn!(
+                    "Hook {:?} failed with {status} on attempt {attempt}, giving up",
+                    hook.command
+                );
+            }
+            None => {}
+        }
+    }
+
+    /// Wait for exited condition processes, without blocking, running the hook of those that
+    /// succeeded, and killing those that timed out
+    fn reap_conditions(&mut self) -> io::Result<()> {
+        let mut i = 0;
+        while let Some(running) = self.conditions.get_mut(i) {
+            let met = if let Some(status) = running.child.try_wait()? {
+                status.success()
+            } else {
+                let timeout = running
+                    .hook
+                    .condition_timeout
+                    .unwrap_or(DEFAULT_CONDITION_TIMEOUT);
+                if running.start.elapsed() < timeout {
+                    i += 1;
+                    continue;
+                }
+                log::warn!(
+                    "Condition command of hook {:?} timed out after {timeout:?}",
+                    running.hook.command
+                );
+                if let Err(err) = running.child.kill().and_then(|()| running.child.wait()) {
+                    log::error!(
+                        "Failed to kill condition command of hook {:?}: {err}",
+                        running.hook.command
+                    );
+                }
+                false
+            };
+            // Dropping the removed condition unmarks the hook as running
+            let RunningCondition {
+                hook, ctx, attempt, ..
+            } = self.conditions.swap_remove(i);
+            if met {
+                self.spawn_process(hook, ctx, attempt);
+            } else {
+                log::info!("Condition of hook {hook:?} is not met with {ctx:?}, skipping");
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for exited processes, without blocking
+    fn reap(&mut self) -> io::Result<()> {
+        self.reap_conditions()?;
+        let mut i = 0;
+        while let Some(running_hook) = self.watched.get_mut(i) {
+            if let Some(rc) = running_hook.child.try_wait()? {
+                if running_hook.timeout_state != TimeoutState::Running {
+                    log::warn!(
+                        "Hook {:?} timed out, process exited with {rc}",
+                        running_hook.hook.command
+                    );
+                } else if let Some(reason) = limits::kill_reason(running_hook.hook, rc) {
+                    log::warn!(
+                        "Hook {:?} process {} exited with {rc}, {reason}",
+                        running_hook.hook.command,
+                        running_hook.child.id()
+                    );
+                } else {
+                    log::info!("Process exited with code {:?}", rc.code());
+                }
+                // Dropping the removed hook unmarks it as running
+                let RunningHook {
+                    hook, ctx, attempt, ..
+                } = self.watched.swap_remove(i);
+                self.schedule_retry(hook, ctx, attempt, rc);
+            } else {
+                running_hook.enforce_timeout();
+                i += 1;
+            }
+        }
+        // Concurrency keys can be as many as files, so forget those of exited processes
+        self.running_hooks
+            .retain(|_, token| token.strong_count() > 0);
+        Ok(())
+    }
+}
+
+/// Reaper thread function, that starts requested hook runs, waits for started processes, and
+/// replays deferred runs
+pub(crate) fn reaper<'a>(
+    mut reaper: Reaper<'a>,
+    rx: &mpsc::Receiver<Request<'a>>,
+) -> anyhow::Result<()> {
+    /// Wait delay for channel recv, only effective if having at least 1 process to watch
+    const REAPER_WAIT_DELAY: Duration = Duration::from_millis(500);
+    loop {
+        let now = jiff::Zoned::now();
+        reaper.replay_deferred(now.datetime());
+        reaper.flush_debounced(now.datetime());
+
+        // Schedules have a minute resolution, so deferred runs are checked on each minute start
+        let deferred_wait_delay = (!reaper.deferred.is_empty()).then(|| {
+            Duration::from_secs(60_u64.saturating_sub(now.second().unsigned_abs().into()))
+        });
+        let watched_wait_delay = (!reaper.watched.is_empty() || !reaper.conditions.is_empty())
+            .then_some(REAPER_WAIT_DELAY);
+        // Due retries and debounced runs of busy hooks are checked again when processes are watched
+        let instant_now = Instant::now();
+        let timer_wait_delay = reaper
+            .retries
+            .iter()
+            .map(|r| r.due)
+            .chain(reaper.debounced.iter().map(Debounced::due))
+            .filter(|due| *due > instant_now)
+            .map(|due| due - instant_now)
+            .min();
+        let received = match deferred_wait_delay
+            .into_iter()
+            .chain(watched_wait_delay)
+            .chain(timer_wait_delay)
+            .min()
+        {
+            None => Some(rx.recv()?),
+            Some(delay) => match rx.recv_timeout(delay) {
+                Ok(req) => Some(req),
+                Err(mpsc::RecvTimeoutError::Timeout) => None,
+                Err(err @ mpsc::RecvTimeoutError::Disconnected) => return Err(err.into()),
+            },
+        };
+        // Start the requests sent meanwhile too, so that a burst of events is handled, and its
+        // deferred runs persisted, at once
+        for req in received.into_iter().chain(rx.try_iter()) {
+            reaper.start(req.hook, req.ctx, jiff::Zoned::now().datetime());
+        }
+        reaper.reap()?;
+        reaper.start_waiting();
+        reaper.start_queued();
+        reaper.start_due_retries();
+        reaper.save_deferred();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::{fs::PermissionsExt as _, process::ExitStatusExt as _};
+
+    use super::*;
+
+    /// Local noon time, to start hook runs at
+    const NOON: civil::DateTime = civil::date(2026, 10, 18).at(12, 0, 0, 0);
+
+    /// Context of an event for `path` in `/data/folder`, without subdirectory
+    fn context(path: Option<&str>) -> Context {
+        Context {
+            event: config::FolderEvent::FileDownSyncDone,
+            path: path.map(PathBuf::from),
+            folder: PathBuf::from("/data/folder"),
+            folder_id: "abcd-1234".to_owned(),
+            folder_label: "Folder".to_owned(),
+            source: None,
+            device_id: None,
+            device_name: None,
+            file: None,
+            subdir: PathBuf::new(),
+            captures: Vec::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Call `done` until it returns true, failing if it takes too long
+    fn poll_until(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Lines of the file at `path`, once it has at least `count` of them, as written from
+    /// another thread
+    fn wait_for_lines(path: &Path, count: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        poll_until(|| {
+            lines = fs::read_to_string(path)
+                .unwrap_or_default()
+                .lines()
+                .map(str::to_owned)
+                .collect();
+            lines.len() >= count
+        });
+        lines
+    }
+
+    /// Hook running `command`
+    fn hook(command: &[&str], allow_concurrent: Option<bool>) -> config::FolderHook {
+        config::FolderHook {
+            name: None,
+            folder: config::FolderSelector::Paths(vec![config::FolderPattern::Any]),
+            event: config::FolderEvent::FileDownSyncDone,
+            filter: None,
+            exclude: None,
+            filter_regex: None,
+            normalize_unicode: None,
+            case_insensitive: None,
+            min_size: None,
+            max_size: None,
+            max_age: None,
+            mime: Vec::new(),
+            modified_by: Vec::new(),
+            when: None,
+            condition: None,
+            condition_timeout: None,
+            schedule: Vec::new(),
+            debounce: None,
+            paths_separator: None,
+            command: shlex::try_join(command.iter().copied())
+                .unwrap()
+                .parse()
+                .unwrap(),
+            shell: None,
+            cwd: None,
+            env: BTreeMap::new(),
+            clear_env: None,
+            keep_env: Vec::new(),
+            log_file: None,
+            log_file_max_size: None,
+            log_file_count: None,
+            user: None,
+            group: None,
+            groups: Vec::new(),
+            credentials: None,
+            umask: None,
+            nice: None,
+            ionice_class: None,
+            ionice_level: None,
+            max_memory: None,
+            max_cpu_time: None,
+            max_open_files: None,
+            max_processes: None,
+            stdin: None,
+            enrich: None,
+            allow_concurrent,
+            on_busy: None,
+            concurrency_key: config::ConcurrencyKey::Hook,
+            priority: 0,
+            timeout: None,
+            timeout_grace: None,
+            retry: None,
+        }
+    }
+
+    /// A hook whose previous run has not been reaped must not run again
+    #[test]
+    fn skip_run_while_previous_run_not_reaped() {
+        let hook = hook(&["true"], None);
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, context(None), NOON);
+        let mut running_hook = reaper.watched.pop().unwrap();
+        running_hook.child.wait().unwrap();
+
+        reaper.start(&hook, context(None), NOON);
+        assert!(reaper.watched.is_empty());
+    }
+
+    /// A hook allowing concurrent runs must spawn a process even while already running
+    #[test]
+    fn concurrent_runs_when_allowed() {
+        let hook = hook(&["true"], Some(true));
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, context(None), NOON);
+        reaper.start(&hook, context(None), NOON);
+
+        for _ in 0..2 {
+            let mut running_hook = reaper.watched.pop().unwrap();
+            running_hook.child.wait().unwrap();
+        }
+    }
+
+    /// The event path and folder must be exported to the hook command environment
+    #[test]
+    fn export_path_and_folder_to_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let script = format!(
+            "printf '%s\\n%s' \"$STFED_PATH\" \"$STFED_FOLDER\" > {out}",
+            out = out.to_str().unwrap()
+        );
+        let hook = hook(&["sh", "-c", &script], None);
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, context(Some("sub/file.txt")), NOON);
+
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "sub/file.txt\n/data/folder"
+        );
+    }
+
+    /// The event, folder, device and hook must be exported to the environment
+    #[test]
+    fn export_event_details_to_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let script = format!(
+            "printf '%s\\n' \"$STFED_HOOK_NAME\" \"$STFED_EVENT\" \"$STFED_EVENT_ID\" \
+             \"$STFED_EVENT_TIME\" \"$STFED_ABS_PATH\" \"$STFED_FOLDER_ID\" \"$STFED_FOLDER_LABEL\" \
+             \"$STFED_DEVICE_ID\" \"$STFED_DEVICE_NAME\" > {out}",
+            out = out.to_str().unwrap()
+        );
+        let hook = config::FolderHook {
+            name: Some("notify".to_owned()),
+            ..hook(&["sh", "-c", &script], None)
+        };
+        let ctx = Context {
+            source: Some(syncthing::ServerEvent {
+                id: 7,
+                global_id: 42,
+                event_type: crate::syncthing_rest::EventType::ItemFinished,
+                time: "2026-10-18T12:00:00Z".to_owned(),
+                data: serde_json::value::RawValue::from_string("{}".to_owned()).unwrap(),
+            }),
+            device_id: Some("BBBBBBB".to_owned()),
+            device_name: Some("laptop".to_owned()),
+            ..context(Some("sub/file.txt"))
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, ctx, NOON);
+
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "notify\nfile_down_sync_done\n7\n2026-10-18T12:00:00Z\n/data/folder/sub/file.txt\n\
+             abcd-1234\nFolder\nBBBBBBB\nlaptop\n"
+        );
+    }
+
+    /// Information of a file as fetched from the server
+    fn file_info() -> syncthing::FileInfo {
+        syncthing::FileInfo {
+            size: 1234,
+            modified: "2026-10-18T11:59:58+02:00".to_owned(),
+            permissions: "0644".to_owned(),
+            version: vec![
+                "BBBBBBB:1760781598".to_owned(),
+                "AAAAAAA:1760780000".to_owned(),
+            ],
+            modified_by: "BBBBBBB".to_owned(),
+            blocks_hash: Some("q83vEjRWeJA=".to_owned()),
+        }
+    }
+
+    /// The information of the event file of enriched runs must be exported to the environment
+    #[test]
+    fn export_file_info_to_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let script = format!(
+            "printf '%s\\n' \"$STFED_FILE_SIZE\" \"$STFED_FILE_MODIFIED\" \
+             \"$STFED_FILE_PERMISSIONS\" \"$STFED_FILE_VERSION\" \"$STFED_FILE_MODIFIED_BY\" \
+             \"$STFED_FILE_BLOCKS_HASH\" > {out}",
+            out = out.to_str().unwrap()
+        );
+        let hook = hook(&["sh", "-c", &script], None);
+        let ctx = Context {
+            file: Some(file_info()),
+            ..context(Some("sub/file.txt"))
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, ctx, NOON);
+
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "1234\n2026-10-18T11:59:58+02:00\n0644\nBBBBBBB:1760781598,AAAAAAA:1760780000\n\
+             BBBBBBB\nq83vEjRWeJA=\n"
+        );
+    }
+
+    /// Placeholders in command arguments must be substituted for each run, without word splitting
+    #[test]
+    fn substitute_command_arguments() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let script = format!("printf '%s\\n' \"$@\" > {out}", out = out.to_str().unwrap());
+        let hook = hook(
+            &[
+                "sh",
+                "-c",
+                &script,
+                "sh",
+                "{path}",
+                "--name={basename}",
+                "{folder_label}:{event}",
+                "{{path}}",
+                "awk '{print $1}'",
+            ],
+            None,
+        );
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, context(Some("sub/my file.txt")), NOON);
+
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "sub/my file.txt\n--name=my file.txt\nFolder:file_down_sync_done\n{path}\nawk '{print $1}'\n"
+        );
+    }
+
+    /// Commands run through a shell must get the placeholders quoted, and run in the configured
+    /// working directory
+    #[test]
+    fn run_through_shell_in_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let hook = config::FolderHook {
+            command: "printf '%s|' {basename} $0 | tr a-z A-Z > out"
+                .parse()
+                .unwrap(),
+            shell: Some(true),
+            cwd: Some(config::WorkingDir::Folder),
+            ..hook(&["true"], None)
+        };
+        let ctx = Context {
+            folder: dir.path().to_path_buf(),
+            ..context(Some("sub/it's $HOME.txt"))
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, ctx, NOON);
+
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("out")).unwrap(),
+            "IT'S $HOME.TXT|/BIN/SH|"
+        );
+    }
+
+    /// The environment of the commands must be cleared if configured, except for the variables
+    /// to keep, and extended with the configured ones
+    #[test]
+    fn set_hook_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let script = format!(
+            "printf '%s\\n' \"$STFED_EVENT\" \"$GREETING\" \"${{HOME-unset}}\" \"${{USER-unset}}\" > {out}",
+            out = out.to_str().unwrap()
+        );
+        let hook = config::FolderHook {
+            env: BTreeMap::from([("GREETING".to_owned(), "hello world".to_owned())]),
+            clear_env: Some(true),
+            keep_env: vec!["HOME".to_owned()],
+            ..hook(&["sh", "-c", &script], None)
+        };
+        let mut reaper = Reaper::new(&[], None, None);
 
-        let token = Arc::new(());
-        running_hooks.insert(hook_id, Arc::downgrade(&token));
-        reaper_tx.send(RunningHook {
-            _token: token,
-            child,
-        })?;
-    } else {
-        log::warn!(
-            "A process is already running for this hook, and allow_concurrent is set for false, ignoring"
+        reaper.start(&hook, context(Some("sub/file.txt")), NOON);
+
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            format!(
+                "file_down_sync_done\nhello world\n{home}\nunset\n",
+                home = env::var("HOME").unwrap()
+            )
         );
     }
 
-    Ok(())
-}
+    /// The output of hook commands must be appended to their log file, with the process id and
+    /// stream of each line
+    #[test]
+    fn capture_output_to_log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_filepath = dir.path().join("hook.log");
+        let hook = config::FolderHook {
+            log_file: Some(log_filepath.clone()),
+            ..hook(&["sh", "-c", "echo to stdout; echo to stderr >&2"], None)
+        };
+        let mut reaper = Reaper::new(&[], None, None);
 
-/// A spawned hook process, whose liveness marks its hook as running
-pub(crate) struct RunningHook {
-    /// Token whose drop unmarks the hook as running
-    _token: Arc<()>,
-    /// The spawned process
-    child: Child,
-}
+        reaper.start(&hook, context(None), NOON);
 
-/// Reaper thread function, that waits for started processes
-pub(crate) fn reaper(rx: &mpsc::Receiver<RunningHook>) -> anyhow::Result<()> {
-    let mut watched: Vec<RunningHook> = Vec::new();
-    loop {
-        /// Wait delay for channel recv, only effective if having at least 1 process to watch
-        const REAPER_WAIT_DELAY: Duration = Duration::from_millis(500);
-        if watched.is_empty() {
-            let new = rx.recv()?;
-            watched.push(new);
-        } else if let Ok(new) = rx.recv_timeout(REAPER_WAIT_DELAY) {
-            watched.push(new);
-        }
-        loop {
-            let mut do_loop = false;
-            for (i, running_hook) in watched.iter_mut().enumerate() {
-                if let Some(rc) = running_hook.child.try_wait()? {
-                    log::info!("Process exited with code {:?}", rc.code());
-                    // Dropping the removed hook unmarks it as running
-                    watched.swap_remove(i);
-                    do_loop = true;
-                    break;
-                }
-            }
-            if !do_loop {
-                break;
-            }
+        let mut running_hook = reaper.watched.pop().unwrap();
+        let pid = running_hook.child.id();
+        assert!(running_hook.child.wait().unwrap().success());
+        let lines = wait_for_lines(&log_filepath, 2);
+        for expected in [
+            format!(" [{pid}] stdout: to stdout"),
+            format!(" [{pid}] stderr: to stderr"),
+        ] {
+            assert!(lines.iter().any(|l| l.ends_with(&expected)), "{lines:?}");
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::{fs, thread, time::Instant};
+    /// Commands must run with the configured user, groups and file mode creation mask
+    #[test]
+    fn run_as_user_with_umask() {
+        if !nix::unistd::Uid::effective().is_root() {
+            // Switching user needs privileges
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let log_filepath = dir.path().join("hook.log");
+        let hook = config::FolderHook {
+            credentials: crate::credentials::Credentials::resolve(
+                Some("nobody"),
+                Some("65533"),
+                &["65532".to_owned()],
+            )
+            .unwrap(),
+            umask: Some(stat::Mode::from_bits_truncate(0o027)),
+            log_file: Some(log_filepath.clone()),
+            ..hook(&["sh", "-c", "id -u; id -g; id -G; umask"], None)
+        };
+        let mut reaper = Reaper::new(&[], None, None);
 
-    use super::*;
+        reaper.start(&hook, context(None), NOON);
 
-    /// Hook running `command`
-    fn hook(command: &[&str], allow_concurrent: Option<bool>) -> config::FolderHook {
-        config::FolderHook {
-            folder: Path::new("/").try_into().unwrap(),
-            event: config::FolderEvent::FileDownSyncDone,
-            filter: None,
-            command: command.iter().map(|a| (*a).to_owned()).collect(),
-            allow_concurrent,
-        }
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        let lines = wait_for_lines(&log_filepath, 4);
+        let outputs: Vec<_> = lines
+            .iter()
+            .map(|l| l.rsplit(": ").next().unwrap())
+            .collect();
+        assert_eq!(outputs, ["65534", "65533", "65533 65532", "0027"]);
     }
 
-    /// A hook whose previous run has not been reaped must not run again
+    /// Commands must run with the configured niceness and resource limits
     #[test]
-    fn skip_run_while_previous_run_not_reaped() {
-        let hook = hook(&["true"], None);
-        let (reaper_tx, reaper_rx) = mpsc::channel();
-        let mut running_hooks = HashMap::new();
+    fn run_with_priority_and_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_filepath = dir.path().join("hook.log");
+        let hook = config::FolderHook {
+            nice: Some(19),
+            max_open_files: Some(64),
+            max_cpu_time: Some(Duration::from_secs(60)),
+            log_file: Some(log_filepath.clone()),
+            ..hook(&["sh", "-c", "nice; ulimit -n; ulimit -t"], None)
+        };
+        let mut reaper = Reaper::new(&[], None, None);
 
-        run(&hook, None, Path::new("/"), &reaper_tx, &mut running_hooks).unwrap();
-        let mut running_hook = reaper_rx.try_recv().unwrap();
-        running_hook.child.wait().unwrap();
+        reaper.start(&hook, context(None), NOON);
 
-        run(&hook, None, Path::new("/"), &reaper_tx, &mut running_hooks).unwrap();
-        assert!(reaper_rx.try_recv().is_err());
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        let lines = wait_for_lines(&log_filepath, 3);
+        let outputs: Vec<_> = lines
+            .iter()
+            .map(|l| l.rsplit(": ").next().unwrap())
+            .collect();
+        assert_eq!(outputs, ["19", "64", "60"]);
     }
 
-    /// A hook allowing concurrent runs must spawn a process even while already running
+    /// Processes killed by signals sent for limits must be reported as such
     #[test]
-    fn concurrent_runs_when_allowed() {
-        let hook = hook(&["true"], Some(true));
-        let (reaper_tx, reaper_rx) = mpsc::channel();
-        let mut running_hooks = HashMap::new();
+    fn report_limit_kills() {
+        let limited = config::FolderHook {
+            max_cpu_time: Some(Duration::from_secs(60)),
+            ..hook(&["true"], None)
+        };
+        let signaled = |signal: Signal| ExitStatus::from_raw(signal as i32);
 
-        run(&hook, None, Path::new("/"), &reaper_tx, &mut running_hooks).unwrap();
-        run(&hook, None, Path::new("/"), &reaper_tx, &mut running_hooks).unwrap();
+        assert_eq!(
+            limits::kill_reason(&limited, signaled(Signal::SIGXCPU)).as_deref(),
+            Some("after exceeding its CPU time limit of 1m")
+        );
+        assert_eq!(
+            limits::kill_reason(&limited, signaled(Signal::SIGKILL)).as_deref(),
+            Some("possibly after exceeding its CPU time limit of 1m, or OOM-killed")
+        );
+        assert_eq!(
+            limits::kill_reason(&limited, signaled(Signal::SIGTERM)),
+            None
+        );
+        assert_eq!(
+            limits::kill_reason(&limited, ExitStatus::from_raw(1 << 8)),
+            None
+        );
 
-        for _ in 0..2 {
-            let mut running_hook = reaper_rx.try_recv().unwrap();
-            running_hook.child.wait().unwrap();
-        }
+        let unlimited = hook(&["true"], None);
+        assert_eq!(
+            limits::kill_reason(&unlimited, signaled(Signal::SIGKILL)).as_deref(),
+            Some("possibly OOM-killed")
+        );
     }
 
-    /// The event path and folder must be exported to the hook command environment
+    /// Without an event path, the exported path variable must be empty
     #[test]
-    fn export_path_and_folder_to_environment() {
+    fn export_empty_path_when_absent() {
         let dir = tempfile::tempdir().unwrap();
         let out = dir.path().join("out");
         let script = format!(
@@ -169,70 +1749,138 @@ mod tests {
             out = out.to_str().unwrap()
         );
         let hook = hook(&["sh", "-c", &script], None);
-        let (reaper_tx, reaper_rx) = mpsc::channel();
-        let mut running_hooks = HashMap::new();
-
-        run(
-            &hook,
-            Some(Path::new("sub/file.txt")),
-            Path::new("/data/folder"),
-            &reaper_tx,
-            &mut running_hooks,
-        )
-        .unwrap();
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, context(None), NOON);
+
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        assert_eq!(fs::read_to_string(&out).unwrap(), "\n/data/folder");
+    }
+
+    /// The event path relative to the subdirectory the hook is scoped to must be exported too
+    #[test]
+    fn export_subdirectory_path_to_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let script = format!(
+            "printf '%s\\n%s' \"$STFED_PATH\" \"$STFED_SUBDIR_PATH\" > {out}",
+            out = out.to_str().unwrap()
+        );
+        let hook = hook(&["sh", "-c", &script], None);
+        let mut reaper = Reaper::new(&[], None, None);
+        let ctx = Context {
+            subdir: PathBuf::from("docs/invoices"),
+            ..context(Some("docs/invoices/2026/march.pdf"))
+        };
+
+        reaper.start(&hook, ctx.clone(), NOON);
 
-        let mut running_hook = reaper_rx.try_recv().unwrap();
+        let mut running_hook = reaper.watched.pop().unwrap();
         assert!(running_hook.child.wait().unwrap().success());
         assert_eq!(
             fs::read_to_string(&out).unwrap(),
-            "sub/file.txt\n/data/folder"
+            "docs/invoices/2026/march.pdf\n2026/march.pdf"
         );
     }
 
-    /// Without an event path, the exported path variable must be empty
+    /// The named groups captured by the regex filter must be exported to the hook environment
     #[test]
-    fn export_empty_path_when_absent() {
+    fn export_regex_captures_to_environment() {
         let dir = tempfile::tempdir().unwrap();
         let out = dir.path().join("out");
         let script = format!(
-            "printf '%s\\n%s' \"$STFED_PATH\" \"$STFED_FOLDER\" > {out}",
+            "printf '%s-%s' \"$STFED_MATCH_YEAR\" \"$STFED_MATCH_MONTH\" > {out}",
             out = out.to_str().unwrap()
         );
         let hook = hook(&["sh", "-c", &script], None);
-        let (reaper_tx, reaper_rx) = mpsc::channel();
-        let mut running_hooks = HashMap::new();
+        let mut reaper = Reaper::new(&[], None, None);
+        let ctx = Context {
+            captures: vec![
+                ("year".to_owned(), "2026".to_owned()),
+                ("month".to_owned(), "03".to_owned()),
+            ],
+            ..context(Some("invoices/2026/03/a.pdf"))
+        };
 
-        run(
-            &hook,
-            None,
-            Path::new("/data/folder"),
-            &reaper_tx,
-            &mut running_hooks,
-        )
-        .unwrap();
+        reaper.start(&hook, ctx.clone(), NOON);
 
-        let mut running_hook = reaper_rx.try_recv().unwrap();
+        let mut running_hook = reaper.watched.pop().unwrap();
         assert!(running_hook.child.wait().unwrap().success());
-        assert_eq!(fs::read_to_string(&out).unwrap(), "\n/data/folder");
+        assert_eq!(fs::read_to_string(&out).unwrap(), "2026-03");
+    }
+
+    /// A hook must not run for a file that does not meet its conditions
+    #[test]
+    fn skip_run_on_unmet_file_condition() {
+        let dir = tempfile::tempdir().unwrap();
+        // PDF magic bytes, with a misleading extension
+        fs::write(dir.path().join("doc.txt"), b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n").unwrap();
+        let ctx = Context {
+            folder: dir.path().to_owned(),
+            ..context(Some("doc.txt"))
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        let met = [
+            config::FolderHook {
+                min_size: Some(bytesize::ByteSize::b(1)),
+                max_size: Some(bytesize::ByteSize::kb(1)),
+                ..hook(&["true"], Some(true))
+            },
+            config::FolderHook {
+                max_age: Some(Duration::from_secs(60)),
+                ..hook(&["true"], Some(true))
+            },
+            config::FolderHook {
+                mime: vec!["image/*".to_owned(), "application/pdf".to_owned()],
+                ..hook(&["true"], Some(true))
+            },
+        ];
+        for hook in &met {
+            assert!(unmet_file_condition(hook, &ctx).is_none());
+        }
+        let unmet = [
+            config::FolderHook {
+                min_size: Some(bytesize::ByteSize::kb(1)),
+                ..hook(&["true"], Some(true))
+            },
+            config::FolderHook {
+                max_size: Some(bytesize::ByteSize::b(1)),
+                ..hook(&["true"], Some(true))
+            },
+            config::FolderHook {
+                mime: vec!["image/*".to_owned()],
+                ..hook(&["true"], Some(true))
+            },
+        ];
+        for hook in &unmet {
+            assert!(unmet_file_condition(hook, &ctx).is_some());
+            reaper.start(hook, ctx.clone(), NOON);
+        }
+        assert!(reaper.watched.is_empty());
+
+        // A file that vanished since the event cannot meet any condition
+        let ctx = Context {
+            path: Some(PathBuf::from("missing.txt")),
+            ..ctx
+        };
+        assert!(unmet_file_condition(&met[0], &ctx).is_some());
     }
 
     /// The reaper must unregister a hook once its process exits, so it can run again
     #[test]
     fn reaper_unregisters_exited_hook() {
         let hook = hook(&["true"], None);
-        let (reaper_tx, reaper_rx) = mpsc::channel();
-        let mut running_hooks = HashMap::new();
+        let mut reaper = Reaper::new(&[], None, None);
 
-        run(&hook, None, Path::new("/"), &reaper_tx, &mut running_hooks).unwrap();
-        assert!(running_hooks.values().any(|t| t.upgrade().is_some()));
+        reaper.start(&hook, context(None), NOON);
+        assert!(reaper.running_hooks.values().any(|t| t.upgrade().is_some()));
 
-        thread::spawn(move || reaper(&reaper_rx));
-
-        let deadline = Instant::now() + Duration::from_secs(5);
-        while running_hooks.values().any(|t| t.upgrade().is_some()) {
-            assert!(Instant::now() < deadline);
-            thread::sleep(Duration::from_millis(10));
-        }
+        poll_until(|| {
+            reaper.reap().unwrap();
+            !reaper.running_hooks.values().any(|t| t.upgrade().is_some())
+        });
     }
 
     /// A hook command that fails to spawn must be logged, not propagated, and must not
@@ -240,13 +1888,12 @@ mod tests {
     #[test]
     fn failed_spawn_is_not_fatal() {
         let hook = hook(&["/nonexistent/hook/command"], None);
-        let (reaper_tx, reaper_rx) = mpsc::channel();
-        let mut running_hooks = HashMap::new();
+        let mut reaper = Reaper::new(&[], None, None);
 
-        run(&hook, None, Path::new("/"), &reaper_tx, &mut running_hooks).unwrap();
+        reaper.start(&hook, context(None), NOON);
 
-        assert!(running_hooks.is_empty());
-        assert!(reaper_rx.try_recv().is_err());
+        assert!(reaper.running_hooks.is_empty());
+        assert!(reaper.watched.is_empty());
     }
 
     /// A reaper channel send failure must be a fatal error
@@ -255,8 +1902,567 @@ mod tests {
         let hook = hook(&["true"], None);
         let (reaper_tx, reaper_rx) = mpsc::channel();
         drop(reaper_rx);
-        let mut running_hooks = HashMap::new();
 
-        assert!(run(&hook, None, Path::new("/"), &reaper_tx, &mut running_hooks).is_err());
+        assert!(run(&hook, context(None), &reaper_tx).is_err());
+    }
+
+    /// A hook must only run if its condition command succeeds in time, with the event
+    /// environment
+    #[test]
+    fn run_only_if_condition_met() {
+        let condition = |condition: &[&str]| config::FolderHook {
+            condition: Some(
+                shlex::try_join(condition.iter().copied())
+                    .unwrap()
+                    .parse()
+                    .unwrap(),
+            ),
+            condition_timeout: Some(Duration::from_millis(200)),
+            ..hook(&["true"], Some(true))
+        };
+
+        for (hook, met) in [
+            (condition(&["true"]), true),
+            (condition(&["false"]), false),
+            (
+                condition(&["sh", "-c", "test \"$STFED_PATH\" = a.txt"]),
+                true,
+            ),
+            (
+                condition(&["sh", "-c", "test \"$STFED_PATH\" = b.txt"]),
+                false,
+            ),
+            (condition(&["sleep", "5"]), false),
+            (condition(&["/nonexistent/condition/command"]), false),
+        ] {
+            let mut reaper = Reaper::new(&[], None, None);
+            let start = Instant::now();
+            reaper.start(&hook, context(Some("a.txt")), NOON);
+            // The condition must not block the reaper
+            assert!(start.elapsed() < Duration::from_millis(100));
+            poll_until(|| {
+                reaper.reap_conditions().unwrap();
+                reaper.conditions.is_empty()
+            });
+            match reaper.watched.pop() {
+                Some(mut running_hook) => {
+                    assert!(met, "{:?}", hook.condition);
+                    running_hook.child.wait().unwrap();
+                }
+                None => assert!(!met, "{:?}", hook.condition),
+            }
+        }
+    }
+
+    /// A run outside of the hook schedule must be deferred, coalesced with the deferred runs
+    /// for the same file, persisted, and replayed once the schedule allows it
+    #[test]
+    fn defer_run_outside_schedule() {
+        let dir = tempfile::tempdir().unwrap();
+        let deferred_filepath = dir.path().join("deferred.json");
+        let hooks = [
+            hook(&["true"], None),
+            config::FolderHook {
+                schedule: vec!["22:00-06:00".parse().unwrap()],
+                ..hook(&["true"], Some(true))
+            },
+        ];
+        let mut reaper = Reaper::new(&hooks, Some(deferred_filepath.clone()), None);
+
+        for path in ["a.txt", "b.txt", "a.txt"] {
+            reaper.start(&hooks[1], context(Some(path)), NOON);
+        }
+        assert!(reaper.watched.is_empty());
+        assert_eq!(reaper.deferred.len(), 2);
+        // Runs are persisted once for all the events handled at once
+        assert!(!deferred_filepath.exists());
+        reaper.save_deferred();
+
+        // Reload from the persisted file, as after a restart
+        drop(reaper);
+        let mut restarted = Reaper::new(&hooks, Some(deferred_filepath.clone()), None);
+        assert_eq!(restarted.deferred.len(), 2);
+        assert!(
+            restarted
+                .deferred
+                .iter()
+                .all(|d| ptr::eq(d.hook, &raw const hooks[1]))
+        );
+
+        restarted.replay_deferred(civil::date(2026, 10, 18).at(21, 59, 0, 0));
+        assert!(restarted.watched.is_empty());
+
+        restarted.replay_deferred(civil::date(2026, 10, 18).at(22, 0, 0, 0));
+        restarted.save_deferred();
+        assert!(restarted.deferred.is_empty());
+        assert_eq!(restarted.watched.len(), 2);
+        for running_hook in &mut restarted.watched {
+            running_hook.child.wait().unwrap();
+        }
+        assert!(
+            Reaper::new(&hooks, Some(deferred_filepath), None)
+                .deferred
+                .is_empty()
+        );
+    }
+
+    /// Persisted deferred runs of hooks whose configuration changed must be dropped
+    #[test]
+    fn drop_deferred_runs_of_changed_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let deferred_filepath = dir.path().join("deferred.json");
+        let schedule = || vec!["22:00-06:00".parse().unwrap()];
+        let hooks = [config::FolderHook {
+            schedule: schedule(),
+            ..hook(&["true"], None)
+        }];
+        let mut reaper = Reaper::new(&hooks, Some(deferred_filepath.clone()), None);
+        reaper.start(&hooks[0], context(None), NOON);
+        reaper.save_deferred();
+
+        let changed_hooks = [config::FolderHook {
+            schedule: schedule(),
+            ..hook(&["false"], None)
+        }];
+        assert!(
+            Reaper::new(&changed_hooks, Some(deferred_filepath), None)
+                .deferred
+                .is_empty()
+        );
+    }
+
+    /// A hook process group must be terminated when its timeout expires
+    #[test]
+    fn terminate_hook_on_timeout() {
+        let hook = config::FolderHook {
+            timeout: Some(Duration::from_millis(100)),
+            ..hook(&["sh", "-c", "sleep 30 & wait"], None)
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, context(None), NOON);
+
+        poll_until(|| {
+            reaper.reap().unwrap();
+            reaper.watched.is_empty()
+        });
+    }
+
+    /// A hook process group ignoring termination must be killed after the grace period
+    #[test]
+    fn kill_hook_ignoring_termination() {
+        let hook = config::FolderHook {
+            timeout: Some(Duration::from_millis(100)),
+            timeout_grace: Some(Duration::from_millis(300)),
+            ..hook(&["sh", "-c", "trap '' TERM; sleep 30"], None)
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+        reaper.start(&hook, context(None), NOON);
+        let mut running_hook = reaper.watched.pop().unwrap();
+
+        running_hook.enforce_timeout();
+        assert_eq!(running_hook.timeout_state, TimeoutState::Running);
+
+        thread::sleep(Duration::from_millis(150));
+        running_hook.enforce_timeout();
+        assert!(matches!(
+            running_hook.timeout_state,
+            TimeoutState::Terminated(_)
+        ));
+        thread::sleep(Duration::from_millis(100));
+        assert!(running_hook.child.try_wait().unwrap().is_none());
+
+        thread::sleep(Duration::from_millis(250));
+        running_hook.enforce_timeout();
+        assert_eq!(running_hook.timeout_state, TimeoutState::Killed);
+        assert_eq!(
+            running_hook.child.wait().unwrap().signal(),
+            Some(Signal::SIGKILL as i32)
+        );
+    }
+
+    /// Retry delays must grow exponentially up to the maximum, for retryable failures only
+    #[test]
+    fn retry_backoff() {
+        let policy = config::RetryPolicy {
+            attempts: 5,
+            backoff: None,
+            max_backoff: Some(Duration::from_secs(100)),
+            exit_codes: vec![],
+        };
+        let failure = ExitStatus::from_raw(1 << 8);
+        assert_eq!(
+            retry_delay(&policy, 1, failure),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry_delay(&policy, 2, failure),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            retry_delay(&policy, 3, failure),
+            Some(Duration::from_secs(100))
+        );
+        assert_eq!(retry_delay(&policy, 5, failure), None);
+        assert_eq!(retry_delay(&policy, 1, ExitStatus::from_raw(0)), None);
+
+        let policy = config::RetryPolicy {
+            exit_codes: vec![75],
+            ..policy
+        };
+        assert_eq!(retry_delay(&policy, 1, failure), None);
+        assert_eq!(
+            retry_delay(&policy, 1, ExitStatus::from_raw(75 << 8)),
+            Some(Duration::from_secs(30))
+        );
+
+        let unbounded = config::RetryPolicy {
+            attempts: 100,
+            backoff: Some(Duration::from_secs(u64::MAX / 2)),
+            max_backoff: None,
+            exit_codes: vec![],
+        };
+        for attempt in [1, 2, 33, 99] {
+            let delay = retry_delay(&unbounded, attempt, failure).unwrap();
+            assert_eq!(delay, MAX_RETRY_BACKOFF);
+            assert!(Instant::now().checked_add(delay).is_some());
+        }
+    }
+
+    /// A retry after many attempts must be scheduled without overflowing
+    #[test]
+    fn schedule_late_retry() {
+        let hook = config::FolderHook {
+            retry: Some(config::RetryPolicy {
+                attempts: 100,
+                backoff: None,
+                max_backoff: None,
+                exit_codes: vec![],
+            }),
+            ..hook(&["false"], None)
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.schedule_retry(&hook, context(None), 40, ExitStatus::from_raw(1 << 8));
+
+        assert_eq!(reaper.retries.len(), 1);
+        assert!(reaper.retries[0].due > Instant::now() + Duration::from_secs(24 * 60 * 60));
+    }
+
+    /// Failed hooks must be run again with the attempt number, until they succeed
+    #[test]
+    fn retry_failed_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("attempts");
+        let hook = config::FolderHook {
+            retry: Some(config::RetryPolicy {
+                attempts: 5,
+                backoff: Some(Duration::from_millis(10)),
+                max_backoff: None,
+                exit_codes: vec![],
+            }),
+            ..hook(
+                &[
+                    "sh",
+                    "-c",
+                    &format!(
+                        "echo $STFED_ATTEMPT >> {}; test $STFED_ATTEMPT -ge 3",
+                        output.display()
+                    ),
+                ],
+                None,
+            )
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, context(None), NOON);
+
+        poll_until(|| {
+            reaper.reap().unwrap();
+            reaper.start_due_retries();
+            reaper.watched.is_empty() && reaper.retries.is_empty()
+        });
+        assert_eq!(fs::read_to_string(&output).unwrap(), "1\n2\n3\n");
+    }
+
+    /// Events of a debounced hook must be collected until quiet, and then run the hook once
+    /// with their paths in a file
+    #[test]
+    fn debounce_bursts_of_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("paths");
+        let hook = config::FolderHook {
+            debounce: Some(Duration::from_millis(100)),
+            paths_separator: Some(config::PathsSeparator::Nul),
+            ..hook(
+                &[
+                    "sh",
+                    "-c",
+                    &format!(
+                        "cp $STFED_PATHS_FILE {0}; echo -n $STFED_PATHS_FILE > {0}.name",
+                        output.display()
+                    ),
+                ],
+                None,
+            )
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        for path in ["a.jpg", "b.jpg", "a.jpg"] {
+            reaper.start(&hook, context(Some(path)), NOON);
+        }
+        reaper.flush_debounced(NOON);
+        assert!(reaper.watched.is_empty());
+
+        thread::sleep(Duration::from_millis(150));
+        reaper.flush_debounced(NOON);
+        assert!(reaper.debounced.is_empty());
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(reaper.watched.is_empty());
+        assert!(running_hook.child.wait().unwrap().success());
+        assert_eq!(fs::read(&output).unwrap(), b"a.jpg\0b.jpg\0");
+
+        let paths_filepath = fs::read_to_string(output.with_extension("name")).unwrap();
+        let mode = fs::metadata(&paths_filepath).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        drop(running_hook);
+        assert!(!Path::new(&paths_filepath).exists());
+    }
+
+    /// Events of a debounced hook collected while it runs must be kept until it exits
+    #[test]
+    fn keep_debounced_events_while_busy() {
+        let hook = config::FolderHook {
+            debounce: Some(Duration::from_millis(10)),
+            ..hook(&["sleep", "0.3"], None)
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, context(Some("a.jpg")), NOON);
+        thread::sleep(Duration::from_millis(20));
+        reaper.flush_debounced(NOON);
+        assert_eq!(reaper.watched.len(), 1);
+
+        for path in ["b.jpg", "c.jpg"] {
+            reaper.start(&hook, context(Some(path)), NOON);
+        }
+        thread::sleep(Duration::from_millis(20));
+        reaper.flush_debounced(NOON);
+        assert_eq!(reaper.watched.len(), 1);
+        assert_eq!(reaper.debounced.len(), 1);
+
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        drop(running_hook);
+        reaper.flush_debounced(NOON);
+        assert!(reaper.debounced.is_empty());
+        assert_eq!(
+            reaper.watched[0].ctx.paths,
+            [PathBuf::from("b.jpg"), PathBuf::from("c.jpg")]
+        );
+    }
+
+    /// Runs for events coming while a hook is running must be queued according to its policy
+    #[test]
+    fn queue_runs_while_busy() {
+        for (on_busy, expected) in [
+            (config::OnBusy::Skip, "a.txt\n"),
+            (config::OnBusy::QueueOne, "a.txt\nc.txt\n"),
+            (config::OnBusy::QueueAll, "a.txt\nb.txt\nc.txt\n"),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let output = dir.path().join("paths");
+            let hook = config::FolderHook {
+                on_busy: Some(on_busy),
+                ..hook(
+                    &[
+                        "sh",
+                        "-c",
+                        &format!("echo $STFED_PATH >> {}; sleep 0.2", output.display()),
+                    ],
+                    None,
+                )
+            };
+            let mut reaper = Reaper::new(&[], None, None);
+
+            for path in ["a.txt", "b.txt", "c.txt"] {
+                reaper.start(&hook, context(Some(path)), NOON);
+            }
+            assert_eq!(reaper.watched.len(), 1);
+
+            poll_until(|| {
+                reaper.reap().unwrap();
+                reaper.start_queued();
+                reaper.watched.is_empty() && reaper.queued.is_empty()
+            });
+            assert_eq!(
+                fs::read_to_string(&output).unwrap(),
+                expected,
+                "{on_busy:?}"
+            );
+        }
+    }
+
+    /// Queued runs must keep their attempt number
+    #[test]
+    fn keep_attempt_of_queued_runs() {
+        let hook = config::FolderHook {
+            on_busy: Some(config::OnBusy::QueueAll),
+            ..hook(&["sleep", "0.1"], None)
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.spawn(&hook, context(Some("a.txt")), 1);
+        reaper.spawn(&hook, context(Some("a.txt")), 3);
+        assert_eq!(reaper.queued.len(), 1);
+
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+        drop(running_hook);
+        reaper.start_queued();
+        assert!(reaper.queued.is_empty());
+        assert_eq!(reaper.watched[0].attempt, 3);
+    }
+
+    /// Runs of a hook must only exclude each other if they have the same concurrency key
+    #[test]
+    fn exclude_runs_with_same_concurrency_key() {
+        for (concurrency_key, expected) in [
+            (config::ConcurrencyKey::Hook, 1),
+            (config::ConcurrencyKey::Path, 2),
+            (
+                config::ConcurrencyKey::Template("{basename}".parse().unwrap()),
+                2,
+            ),
+            (config::ConcurrencyKey::Folder, 1),
+        ] {
+            let hook = config::FolderHook {
+                concurrency_key,
+                ..hook(&["sleep", "30"], None)
+            };
+            let mut reaper = Reaper::new(&[], None, None);
+
+            for path in ["a.txt", "b.txt", "a.txt"] {
+                reaper.start(&hook, context(Some(path)), NOON);
+            }
+
+            assert_eq!(reaper.watched.len(), expected, "{:?}", hook.concurrency_key);
+            for mut running_hook in reaper.watched.drain(..) {
+                running_hook.child.kill().unwrap();
+                running_hook.child.wait().unwrap();
+            }
+        }
+    }
+
+    /// Runs beyond the maximum number of running hooks must wait for a free slot, and start by
+    /// priority
+    #[test]
+    fn wait_for_free_slot_by_priority() {
+        let low = hook(&["sleep", "30"], Some(true));
+        let high = config::FolderHook {
+            priority: 5,
+            ..hook(&["sleep", "30"], Some(true))
+        };
+        let mut reaper = Reaper::new(&[], None, NonZeroUsize::new(1));
+
+        reaper.start(&low, context(Some("a.txt")), NOON);
+        reaper.start(&low, context(Some("b.txt")), NOON);
+        reaper.start(&high, context(Some("c.txt")), NOON);
+
+        assert_eq!(reaper.watched.len(), 1);
+        let waiting: Vec<_> = reaper
+            .waiting
+            .iter()
+            .map(|w| w.ctx.path.clone().unwrap())
+            .collect();
+        assert_eq!(waiting, [PathBuf::from("c.txt"), PathBuf::from("b.txt")]);
+
+        reaper.start_waiting();
+        assert_eq!(reaper.watched.len(), 1);
+
+        let mut first = reaper.watched.pop().unwrap();
+        first.child.kill().unwrap();
+        first.child.wait().unwrap();
+        reaper.start_waiting();
+        assert_eq!(reaper.watched.len(), 1);
+        assert_eq!(reaper.waiting.len(), 1);
+        assert_eq!(reaper.watched[0].ctx.path, Some(PathBuf::from("c.txt")));
+
+        for mut running_hook in reaper.watched.drain(..) {
+            running_hook.child.kill().unwrap();
+            running_hook.child.wait().unwrap();
+        }
+    }
+
+    /// Hooks reading their input must get a JSON document describing the event
+    #[test]
+    fn write_event_json_to_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("stdin.json");
+        let hook = config::FolderHook {
+            name: Some("save-input".to_owned()),
+            stdin: Some(config::Stdin::Json),
+            ..hook(&["sh", "-c", &format!("cat > {}", output.display())], None)
+        };
+        let ctx = Context {
+            captures: vec![("year".to_owned(), "2026".to_owned())],
+            source: Some(syncthing::ServerEvent {
+                id: 7,
+                global_id: 42,
+                event_type: crate::syncthing_rest::EventType::ItemFinished,
+                time: "2026-10-18T12:00:00Z".to_owned(),
+                data: serde_json::value::RawValue::from_string(
+                    r#"{"item":"docs/a.txt","folder":"abcd-1234"}"#.to_owned(),
+                )
+                .unwrap(),
+            }),
+            device_id: Some("BBBBBBB".to_owned()),
+            device_name: Some("laptop".to_owned()),
+            file: Some(file_info()),
+            ..context(Some("docs/a.txt"))
+        };
+        let mut reaper = Reaper::new(&[], None, None);
+
+        reaper.start(&hook, ctx, NOON);
+        let mut running_hook = reaper.watched.pop().unwrap();
+        assert!(running_hook.child.wait().unwrap().success());
+
+        let doc: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(
+            doc,
+            serde_json::json!({
+                "version": 1,
+                "hook": "save-input",
+                "event": "file_down_sync_done",
+                "folder": {
+                    "id": "abcd-1234",
+                    "label": "Folder",
+                    "path": "/data/folder",
+                },
+                "path": "docs/a.txt",
+                "abs_path": "/data/folder/docs/a.txt",
+                "subdir_path": "docs/a.txt",
+                "captures": {"year": "2026"},
+                "paths": [],
+                "attempt": 1,
+                "device": {"id": "BBBBBBB", "name": "laptop"},
+                "file": {
+                    "size": 1234,
+                    "modified": "2026-10-18T11:59:58+02:00",
+                    "permissions": "0644",
+                    "version": ["BBBBBBB:1760781598", "AAAAAAA:1760780000"],
+                    "modified_by": "BBBBBBB",
+                    "blocks_hash": "q83vEjRWeJA=",
+                },
+                "syncthing_event": {
+                    "id": 7,
+                    "globalID": 42,
+                    "type": "ItemFinished",
+                    "time": "2026-10-18T12:00:00Z",
+                    "data": {"item": "docs/a.txt", "folder": "abcd-1234"},
+                },
+            })
+        );
     }
 }
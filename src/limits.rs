@@ -0,0 +1,129 @@
+//! Scheduling priority and resource limits of hook commands
+
+use std::{io, os::unix::process::ExitStatusExt as _, process::ExitStatus};
+
+use nix::{
+    errno::Errno,
+    libc,
+    sys::{
+        resource::{self, Resource},
+        signal::Signal,
+    },
+};
+
+use crate::config;
+
+/// `ioprio_set` target of a single process
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+/// Shift of the class in an I/O priority, above the level
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+/// Default I/O priority level in a class, as for processes that did not set one
+#[cfg(target_os = "linux")]
+const DEFAULT_IONICE_LEVEL: u8 = 4;
+
+/// Scheduling priority and resource limits to apply to a process
+pub(crate) struct Limits {
+    /// Niceness
+    nice: Option<i32>,
+    /// I/O priority, encoded as for `ioprio_set`
+    #[cfg(target_os = "linux")]
+    io_priority: Option<libc::c_int>,
+    /// Resource limits, with their soft and hard values
+    rlimits: Vec<(Resource, resource::rlim_t, resource::rlim_t)>,
+}
+
+impl Limits {
+    /// Scheduling priority and resource limits of a hook, `None` if it has none
+    pub(crate) fn new(hook: &config::FolderHook) -> Option<Self> {
+        let mut rlimits = Vec::new();
+        if let Some(max_memory) = hook.max_memory {
+            rlimits.push((
+                Resource::RLIMIT_AS,
+                max_memory.as_u64(),
+                max_memory.as_u64(),
+            ));
+        }
+        if let Some(max_cpu_time) = hook.max_cpu_time {
+            // The hard limit kills the process, keep a second for it to get SIGXCPU first
+            let secs = max_cpu_time.as_secs().max(1);
+            rlimits.push((Resource::RLIMIT_CPU, secs, secs + 1));
+        }
+        if let Some(max_open_files) = hook.max_open_files {
+            rlimits.push((Resource::RLIMIT_NOFILE, max_open_files, max_open_files));
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(max_processes) = hook.max_processes {
+            rlimits.push((Resource::RLIMIT_NPROC, max_processes, max_processes));
+        }
+        #[cfg(target_os = "linux")]
+        let io_priority = (hook.ionice_class.is_some() || hook.ionice_level.is_some()).then(|| {
+            let class = match hook.ionice_class.unwrap_or_default() {
+                config::IoniceClass::Realtime => 1,
+                config::IoniceClass::BestEffort => 2,
+                config::IoniceClass::Idle => 3,
+            };
+            let level = hook.ionice_level.unwrap_or(DEFAULT_IONICE_LEVEL);
+            (class << IOPRIO_CLASS_SHIFT) | libc::c_int::from(level)
+        });
+        #[cfg(target_os = "linux")]
+        let has_io_priority = io_priority.is_some();
+        #[cfg(not(target_os = "linux"))]
+        let has_io_priority = false;
+        (hook.nice.is_some() || has_io_priority || !rlimits.is_empty()).then_some(Self {
+            nice: hook.nice,
+            #[cfg(target_os = "linux")]
+            io_priority,
+            rlimits,
+        })
+    }
+
+    /// Apply to the current process
+    ///
+    /// Only makes async-signal-safe calls, to be called between fork and exec.
+    pub(crate) fn apply(&self) -> io::Result<()> {
+        for &(resource, soft_limit, hard_limit) in &self.rlimits {
+            resource::setrlimit(resource, soft_limit, hard_limit)?;
+        }
+        if let Some(nice) = self.nice {
+            // SAFETY: system call without pointers
+            let res = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+            Errno::result(res)?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(io_priority) = self.io_priority {
+            // SAFETY: system call without pointers, that libc has no wrapper for
+            let res =
+                unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, io_priority) };
+            Errno::result(res)?;
+        }
+        Ok(())
+    }
+}
+
+/// Likely reason a hook process was killed by a signal, if its exit status shows it may have been
+/// killed for hitting a limit
+///
+/// Other limits make system calls fail instead, ie. memory allocations for `max_memory`.
+pub(crate) fn kill_reason(hook: &config::FolderHook, status: ExitStatus) -> Option<String> {
+    let cpu_time_limit = || {
+        hook.max_cpu_time.map_or_else(
+            || "CPU time limit".to_owned(),
+            |t| format!("CPU time limit of {}", humantime::format_duration(t)),
+        )
+    };
+    match Signal::try_from(status.signal()?).ok()? {
+        Signal::SIGXCPU => Some(format!("after exceeding its {}", cpu_time_limit())),
+        // The kernel kills processes ignoring SIGXCPU at the hard limit, but so do the
+        // out-of-memory killer and users
+        Signal::SIGKILL if hook.max_cpu_time.is_some() => Some(format!(
+            "possibly after exceeding its {}, or OOM-killed",
+            cpu_time_limit()
+        )),
+        Signal::SIGKILL => Some("possibly OOM-killed".to_owned()),
+        _ => None,
+    }
+}
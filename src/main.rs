@@ -1,8 +1,10 @@
 //! Syncthing Folder Event Daemon
 
 use std::{
+    cell::OnceCell,
     collections::hash_map::{Entry, HashMap},
-    io,
+    fs, io,
+    path::{Path, PathBuf},
     rc::Rc,
     sync::{LazyLock, mpsc},
     thread,
@@ -13,9 +15,15 @@ use anyhow::Context as _;
 use config::NormalizedPath;
 
 mod config;
+mod credentials;
+mod expr;
 mod hook;
+mod limits;
+mod output;
+mod schedule;
 mod syncthing;
 mod syncthing_rest;
+mod template;
 
 /// Delay to wait for before trying to reconnect to Synthing server
 const RECONNECT_DELAY: Duration = Duration::from_secs(5);
@@ -28,6 +36,255 @@ static CONFLICT_MATCHER: LazyLock<globset::GlobMatcher> = LazyLock::new(|| {
         .compile_matcher()
 });
 
+/// Hook resolved to a Syncthing folder
+struct FolderHookMatch<'a> {
+    /// Hook configuration
+    hook: &'a config::FolderHook,
+    /// Id of the folder
+    folder_id: String,
+    /// Label of the folder
+    folder_label: String,
+    /// Subdirectory the hook is scoped to, relative to the folder, empty for the whole folder
+    subdir: PathBuf,
+    /// Short ids of the devices the event file must have been last modified by, `None` for any
+    modified_by: Option<Vec<String>>,
+}
+
+impl FolderHookMatch<'_> {
+    /// Event path relative to the subdirectory the hook is scoped to, `None` if outside of it
+    fn subdir_path<'p>(&self, path: &'p Path) -> Option<&'p Path> {
+        path.strip_prefix(&self.subdir).ok()
+    }
+
+    /// Information of the event file, fetched from the server in `file_info`, only once for all
+    /// the hooks of the event
+    fn file_info<'c>(
+        &self,
+        client: &syncthing::Client,
+        path: &Path,
+        file_info: &'c OnceCell<Option<syncthing::FileInfo>>,
+    ) -> Option<&'c syncthing::FileInfo> {
+        file_info
+            .get_or_init(|| {
+                client
+                    .file_info(&self.folder_id, path)
+                    .inspect_err(|err| {
+                        log::error!("Unable to get the information of {path:?}: {err}");
+                    })
+                    .ok()
+            })
+            .as_ref()
+    }
+
+    /// Whether the event file was changed by one of the devices of the hook, `device` being the
+    /// short id of the device that changed it, if any
+    fn is_modified_by(&self, device: Option<&str>) -> bool {
+        let Some(devices) = &self.modified_by else {
+            return true;
+        };
+        device.is_some_and(|d| devices.iter().any(|d2| d2 == d))
+    }
+
+    /// Whether the `when` condition of the hook, if any, is met for an event of `folder`, about
+    /// `path` if any changed by `device`, coming from the server event `source`
+    fn is_when_met(
+        &self,
+        event: &config::FolderEvent,
+        path: Option<&Path>,
+        device: Option<&str>,
+        folder: &Path,
+        source: &syncthing::ServerEvent,
+    ) -> bool {
+        let Some(when) = &self.hook.when else {
+            return true;
+        };
+        let met = when.eval(&WhenContext {
+            hook: self,
+            event,
+            path,
+            device,
+            folder,
+            source,
+        });
+        if !met {
+            log::debug!("Condition of hook {:?} is not met for {path:?}", self.hook);
+        }
+        met
+    }
+
+    /// Context to run the hook for an event of `folder`, about `path` if any changed by `device`,
+    /// coming from the server event `source`
+    #[expect(clippy::too_many_arguments)]
+    fn context(
+        &self,
+        event: config::FolderEvent,
+        path: Option<&Path>,
+        device: Option<&str>,
+        folder: &Path,
+        source: &syncthing::ServerEvent,
+        client: &syncthing::Client,
+        file_info: &OnceCell<Option<syncthing::FileInfo>>,
+    ) -> hook::Context {
+        let device_name = device.and_then(|id| {
+            let server_config = client
+                .server_config()
+                .inspect_err(|err| log::error!("Unable to get the server configuration: {err}"))
+                .ok()?;
+            server_config.device(id).map(|d| d.name.clone())
+        });
+        let file = path
+            .filter(|_| self.hook.enrich.unwrap_or(false))
+            .and_then(|p| self.file_info(client, p, file_info))
+            .cloned();
+        hook::Context {
+            event,
+            path: path.map(Path::to_path_buf),
+            folder: folder.to_path_buf(),
+            folder_id: self.folder_id.clone(),
+            folder_label: self.folder_label.clone(),
+            source: Some(source.clone()),
+            device_id: device.map(str::to_owned),
+            device_name,
+            file,
+            subdir: self.subdir.clone(),
+            captures: Vec::new(),
+            paths: Vec::new(),
+        }
+    }
+}
+
+/// Event context to evaluate `when` conditions of a hook against
+struct WhenContext<'a> {
+    /// Hook the condition is evaluated for
+    hook: &'a FolderHookMatch<'a>,
+    /// Event kind
+    event: &'a config::FolderEvent,
+    /// Event file path relative to the folder, if any
+    path: Option<&'a Path>,
+    /// Short id of the device that changed the event file, if any
+    device: Option<&'a str>,
+    /// Local path of the folder
+    folder: &'a Path,
+    /// Server event the event comes from
+    source: &'a syncthing::ServerEvent,
+}
+
+impl expr::Context for WhenContext<'_> {
+    fn value(&self, field: expr::Field) -> expr::Value {
+        match field {
+            expr::Field::Event => expr::Value::Str(self.event.name().to_owned()),
+            expr::Field::Path => expr::Value::Str(
+                self.path
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            ),
+            expr::Field::Folder => expr::Value::Str(self.folder.to_string_lossy().into_owned()),
+            expr::Field::FolderId => expr::Value::Str(self.hook.folder_id.clone()),
+            expr::Field::FolderLabel => expr::Value::Str(self.hook.folder_label.clone()),
+            expr::Field::Size => expr::Value::Int(
+                self.path
+                    .and_then(|p| fs::metadata(self.folder.join(p)).ok())
+                    .map_or(0, |m| i64::try_from(m.len()).unwrap_or(i64::MAX)),
+            ),
+            expr::Field::Device => expr::Value::Str(self.device.unwrap_or_default().to_owned()),
+            // Item events have the error at the top, folder summaries the reason a folder is
+            // invalid
+            expr::Field::Error => expr::Value::Str(
+                ["/error", "/summary/invalid"]
+                    .into_iter()
+                    .find_map(|p| self.source.data_str(p).filter(|e| !e.is_empty()))
+                    .unwrap_or_default(),
+            ),
+            // Folder summaries have the current state, state changes the new one
+            expr::Field::State => expr::Value::Str(
+                ["/summary/state", "/to"]
+                    .into_iter()
+                    .find_map(|p| self.source.data_str(p))
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+/// Hooks by event and local folder path
+type HooksMap<'a> = HashMap<(config::FolderEvent, Rc<NormalizedPath>), Vec<FolderHookMatch<'a>>>;
+
+/// Short ids of the devices with the given ids or names
+fn resolve_devices(devices: &[String], server_config: &syncthing::ServerConfig) -> Vec<String> {
+    devices
+        .iter()
+        .map(|device| {
+            let id = server_config
+                .devices
+                .iter()
+                .find(|d| {
+                    (d.name == *device)
+                        || (d.id == *device)
+                        || (syncthing::short_device_id(&d.id) == device)
+                })
+                .map_or_else(
+                    || {
+                        log::warn!("Device {device:?} is not configured, assuming it is an id");
+                        device.as_str()
+                    },
+                    |d| d.id.as_str(),
+                );
+            syncthing::short_device_id(id).to_owned()
+        })
+        .collect()
+}
+
+/// Build hook map for fast matching, resolving the folders and devices of each hook against the
+/// configuration of the server
+fn build_hooks_map<'a>(
+    hooks: &'a [config::FolderHook],
+    server_config: &syncthing::ServerConfig,
+) -> HooksMap<'a> {
+    let folders: Vec<(&syncthing::Folder, Rc<NormalizedPath>)> = server_config
+        .folders
+        .iter()
+        .filter_map(|f| {
+            f.path
+                .as_path()
+                .try_into()
+                .map(|p| (f, Rc::new(p)))
+                .inspect_err(|err| log::warn!("Ignoring folder {:?}: {err}", f.path))
+                .ok()
+        })
+        .collect();
+    let mut hooks_map: HooksMap = HashMap::new();
+    for hook in hooks {
+        let modified_by = (!hook.modified_by.is_empty())
+            .then(|| resolve_devices(&hook.modified_by, server_config));
+        let mut matched = false;
+        for (folder, path) in &folders {
+            let Some(subdir) = hook.folder.subdir(&folder.id, &folder.label, path) else {
+                continue;
+            };
+            matched = true;
+            let hook_match = FolderHookMatch {
+                hook,
+                folder_id: folder.id.clone(),
+                folder_label: folder.label.clone(),
+                subdir,
+                modified_by: modified_by.clone(),
+            };
+            match hooks_map.entry((hook.event.clone(), Rc::clone(path))) {
+                Entry::Occupied(mut e) => {
+                    e.get_mut().push(hook_match);
+                }
+                Entry::Vacant(e) => {
+                    e.insert(vec![hook_match]);
+                }
+            }
+        }
+        if !matched {
+            log::warn!("No Syncthing folder matches hook {hook:?}");
+        }
+    }
+    hooks_map
+}
+
 #[expect(clippy::too_many_lines)]
 fn main() -> anyhow::Result<()> {
     // Init logger
@@ -44,152 +301,233 @@ fn main() -> anyhow::Result<()> {
     // Parse config
     let (cfg, hooks) = config::parse().context("Failed to read local config")?;
 
-    // Build hook map for fast matching
-    let mut hooks_map: HashMap<
-        (config::FolderEvent, Rc<NormalizedPath>),
-        Vec<&config::FolderHook>,
-    > = HashMap::new();
-    for hook in &hooks.hooks {
-        match hooks_map.entry((hook.event.clone(), Rc::new(hook.folder.clone()))) {
-            Entry::Occupied(mut e) => {
-                e.get_mut().push(hook);
-            }
-            Entry::Vacant(e) => {
-                e.insert(vec![hook]);
-            }
-        }
-    }
+    // Setup hook runs state, with the runs deferred by a previous instance
+    let deferred_filepath = config::state_filepath("deferred.json")
+        .inspect_err(|err| log::error!("Deferred hook runs will not be persisted: {err:#}"))
+        .ok();
+    let reaper = hook::Reaper::new(&hooks.hooks, deferred_filepath, cfg.max_concurrent_hooks);
+
+    thread::scope(|scope| {
+        // Create reaper thread and channel
+        let (reaper_tx, reaper_rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("reaper".to_owned())
+            .spawn_scoped(scope, move || hook::reaper(reaper, &reaper_rx))?;
 
-    // Setup running hooks state
-    let mut running_hooks = HashMap::new();
-
-    // Create reaper thread and channel
-    let (reaper_tx, reaper_rx) = mpsc::channel();
-    thread::Builder::new()
-        .name("reaper".to_owned())
-        .spawn(move || -> anyhow::Result<()> { hook::reaper(&reaper_rx) })?;
-
-    // Position reached in the event stream, to resume it where it stopped when the connection
-    // is lost
-    let mut cursor = None;
-
-    loop {
-        // Setup client
-        let client_res = syncthing::Client::new(&cfg);
-        match client_res {
-            Ok(client) => {
-                // Event loop
-                let mut events = client.iter_events(cursor.as_ref());
-                for event in &mut events {
-                    // Handle special events
-                    let event = match &event {
-                        Err(err) => {
-                            if let Some(err) = err.downcast_ref::<syncthing::ServerGone>() {
-                                log::warn!(
-                                    "Syncthing server is gone, will restart main loop. {err:?}"
-                                );
-                                break;
-                            } else if let Some(err) =
-                                err.downcast_ref::<syncthing::ServerConfigChanged>()
-                            {
-                                log::warn!(
-                                    "Syncthing server configuration changed, will restart main loop. {err:?}"
-                                );
-                                break;
+        // Position reached in the event stream, to resume it where it stopped when the connection
+        // is lost
+        let mut cursor = None;
+
+        loop {
+            // Setup client, and build hook map for fast matching, the server configuration is
+            // fetched again on each connection, so folders and devices are resolved again after it
+            // changes
+            let client_res = syncthing::Client::new(&cfg).and_then(|client| {
+                let hooks_map = build_hooks_map(&hooks.hooks, &*client.server_config()?);
+                Ok((client, hooks_map))
+            });
+            match client_res {
+                Ok((client, hooks_map)) => {
+                    // Event loop
+                    let mut events = client.iter_events(cursor.take());
+                    for event in &mut events {
+                        // Handle special events
+                        let (event, source) = match &event {
+                            Err(err) => {
+                                if let Some(err) = err.downcast_ref::<syncthing::ServerGone>() {
+                                    log::warn!(
+                                        "Syncthing server is gone, will restart main loop. {err:?}"
+                                    );
+                                    break;
+                                } else if let Some(err) =
+                                    err.downcast_ref::<syncthing::ServerConfigChanged>()
+                                {
+                                    log::warn!(
+                                        "Syncthing server configuration changed, will restart main loop. {err:?}"
+                                    );
+                                    break;
+                                }
+                                event?;
+                                unreachable!();
                             }
-                            event?;
-                            unreachable!();
-                        }
-                        Ok(event) => event,
-                    };
-                    log::info!("New event: {event:?}");
-
-                    // Resolve the local path of the event folder
-                    let (syncthing::Event::FileDownSyncDone { folder, .. }
-                    | syncthing::Event::FolderDownSyncDone { folder }
-                    | syncthing::Event::FileConflict { folder, .. }) = event;
-                    let folder: Rc<NormalizedPath> = match folder.as_path().try_into() {
-                        Ok(folder) => Rc::new(folder),
-                        Err(err) => {
-                            log::error!("Ignoring event {event:?}: {err}");
-                            continue;
-                        }
-                    };
-
-                    // Dispatch event
-                    match event {
-                        syncthing::Event::FileDownSyncDone { path, .. } => {
-                            for hook in hooks_map
-                                .get(&(config::FolderEvent::FileDownSyncDone, Rc::clone(&folder)))
-                                .unwrap_or(&vec![])
-                            {
-                                if hook.filter.as_ref().is_none_or(|g| g.is_match(path)) {
-                                    hook::run(
-                                        hook,
-                                        Some(path),
-                                        &folder,
-                                        &reaper_tx,
-                                        &mut running_hooks,
-                                    )?;
+                            Ok((event, source)) => (event, source),
+                        };
+                        log::info!("New event: {event:?}");
+
+                        // Resolve the local path of the event folder
+                        let (syncthing::Event::FileDownSyncDone { folder, .. }
+                        | syncthing::Event::FolderDownSyncDone { folder }
+                        | syncthing::Event::FileConflict { folder, .. }) = event;
+                        let folder: Rc<NormalizedPath> = match folder.as_path().try_into() {
+                            Ok(folder) => Rc::new(folder),
+                            Err(err) => {
+                                log::error!("Ignoring event {event:?}: {err}");
+                                continue;
+                            }
+                        };
+
+                        // Dispatch event
+                        let file_info = OnceCell::new();
+                        match event {
+                            syncthing::Event::FileDownSyncDone {
+                                path, modified_by, ..
+                            } => {
+                                for hook in hooks_map
+                                    .get(&(
+                                        config::FolderEvent::FileDownSyncDone,
+                                        Rc::clone(&folder),
+                                    ))
+                                    .unwrap_or(&vec![])
+                                {
+                                    let Some(captures) = hook
+                                        .subdir_path(path)
+                                        .and_then(|p| hook.hook.match_path(p))
+                                    else {
+                                        continue;
+                                    };
+                                    if !hook.is_modified_by(Some(modified_by))
+                                        || !hook.is_when_met(
+                                            &config::FolderEvent::FileDownSyncDone,
+                                            Some(path),
+                                            Some(modified_by),
+                                            &folder,
+                                            source,
+                                        )
+                                    {
+                                        continue;
+                                    }
+                                    let ctx = hook::Context {
+                                        captures,
+                                        ..hook.context(
+                                            config::FolderEvent::FileDownSyncDone,
+                                            Some(path),
+                                            Some(modified_by),
+                                            &folder,
+                                            source,
+                                            &client,
+                                            &file_info,
+                                        )
+                                    };
+                                    hook::run(hook.hook, ctx, &reaper_tx)?;
+                                }
+                                for hook in hooks_map
+                                    .get(&(
+                                        config::FolderEvent::RemoteFileConflict,
+                                        Rc::clone(&folder),
+                                    ))
+                                    .unwrap_or(&vec![])
+                                {
+                                    if hook.subdir_path(path).is_some()
+                                        && CONFLICT_MATCHER.is_match(path)
+                                        && hook.is_modified_by(Some(modified_by))
+                                        && hook.is_when_met(
+                                            &config::FolderEvent::RemoteFileConflict,
+                                            Some(path),
+                                            Some(modified_by),
+                                            &folder,
+                                            source,
+                                        )
+                                    {
+                                        hook::run(
+                                            hook.hook,
+                                            hook.context(
+                                                config::FolderEvent::RemoteFileConflict,
+                                                Some(path),
+                                                Some(modified_by),
+                                                &folder,
+                                                source,
+                                                &client,
+                                                &file_info,
+                                            ),
+                                            &reaper_tx,
+                                        )?;
+                                    }
                                 }
                             }
-                            for hook in hooks_map
-                                .get(&(config::FolderEvent::RemoteFileConflict, Rc::clone(&folder)))
-                                .unwrap_or(&vec![])
-                            {
-                                if CONFLICT_MATCHER.is_match(path) {
-                                    hook::run(
-                                        hook,
-                                        Some(path),
+                            syncthing::Event::FolderDownSyncDone { .. } => {
+                                for hook in hooks_map
+                                    .get(&(
+                                        config::FolderEvent::FolderDownSyncDone,
+                                        Rc::clone(&folder),
+                                    ))
+                                    .unwrap_or(&vec![])
+                                {
+                                    if hook.is_when_met(
+                                        &config::FolderEvent::FolderDownSyncDone,
+                                        None,
+                                        None,
                                         &folder,
-                                        &reaper_tx,
-                                        &mut running_hooks,
-                                    )?;
+                                        source,
+                                    ) {
+                                        hook::run(
+                                            hook.hook,
+                                            hook.context(
+                                                config::FolderEvent::FolderDownSyncDone,
+                                                None,
+                                                None,
+                                                &folder,
+                                                source,
+                                                &client,
+                                                &file_info,
+                                            ),
+                                            &reaper_tx,
+                                        )?;
+                                    }
                                 }
                             }
-                        }
-                        syncthing::Event::FolderDownSyncDone { .. } => {
-                            for hook in hooks_map
-                                .get(&(config::FolderEvent::FolderDownSyncDone, Rc::clone(&folder)))
-                                .unwrap_or(&vec![])
-                            {
-                                hook::run(hook, None, &folder, &reaper_tx, &mut running_hooks)?;
-                            }
-                        }
-                        syncthing::Event::FileConflict { path, .. } => {
-                            for hook in hooks_map
-                                .get(&(config::FolderEvent::FileConflict, Rc::clone(&folder)))
-                                .unwrap_or(&vec![])
-                            {
-                                hook::run(
-                                    hook,
-                                    Some(path),
-                                    &folder,
-                                    &reaper_tx,
-                                    &mut running_hooks,
-                                )?;
+                            syncthing::Event::FileConflict { path, .. } => {
+                                for hook in hooks_map
+                                    .get(&(config::FolderEvent::FileConflict, Rc::clone(&folder)))
+                                    .unwrap_or(&vec![])
+                                {
+                                    if hook.subdir_path(path).is_some()
+                                        && hook.is_when_met(
+                                            &config::FolderEvent::FileConflict,
+                                            Some(path),
+                                            None,
+                                            &folder,
+                                            source,
+                                        )
+                                    {
+                                        hook::run(
+                                            hook.hook,
+                                            hook.context(
+                                                config::FolderEvent::FileConflict,
+                                                Some(path),
+                                                None,
+                                                &folder,
+                                                source,
+                                                &client,
+                                                &file_info,
+                                            ),
+                                            &reaper_tx,
+                                        )?;
+                                    }
+                                }
                             }
                         }
                     }
+                    cursor = events.cursor();
                 }
-                cursor = events.cursor();
+                #[expect(clippy::ref_patterns)]
+                Err(ref err) => match err.root_cause().downcast_ref::<ureq::Error>() {
+                    Some(ureq::Error::Io(err2))
+                        if err2.kind() == io::ErrorKind::ConnectionRefused =>
+                    {
+                        log::warn!(
+                            "Syncthing server connection failed, will restart main loop. {err:?}"
+                        );
+                    }
+                    _ => {
+                        client_res?;
+                    }
+                },
             }
-            #[expect(clippy::ref_patterns)]
-            Err(ref err) => match err.root_cause().downcast_ref::<ureq::Error>() {
-                Some(ureq::Error::Io(err2)) if err2.kind() == io::ErrorKind::ConnectionRefused => {
-                    log::warn!(
-                        "Syncthing server connection failed, will restart main loop. {err:?}"
-                    );
-                }
-                _ => {
-                    client_res?;
-                }
-            },
-        }
 
-        log::info!("Will reconnect in {RECONNECT_DELAY:?}");
-        thread::sleep(RECONNECT_DELAY);
-    }
+            log::info!("Will reconnect in {RECONNECT_DELAY:?}");
+            thread::sleep(RECONNECT_DELAY);
+        }
+    })
 }
 
 #[cfg(test)]
@@ -204,4 +542,164 @@ mod tests {
         assert!(!CONFLICT_MATCHER.is_match("doc.txt"));
         assert!(!CONFLICT_MATCHER.is_match("sync-conflict.txt"));
     }
+
+    /// Server configuration with the given folders, and a single remote device
+    fn server_config(folders: Vec<syncthing::Folder>) -> syncthing::ServerConfig {
+        syncthing::ServerConfig {
+            folders,
+            devices: vec![syncthing::Device {
+                id: "SCANNER-DEVICE2".to_owned(),
+                name: "scanner".to_owned(),
+            }],
+        }
+    }
+
+    /// Hooks selecting their folder by id or label must be keyed by its local path
+    #[test]
+    fn resolve_hook_folders_by_id_and_label() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        let hooks: config::FolderConfig = toml::from_str(&format!(
+            r#"
+            [[hooks]]
+            folder_id = "fid1"
+            event = "folder_down_sync_done"
+            command = "true"
+
+            [[hooks]]
+            folder_label = "Photos"
+            event = "folder_down_sync_done"
+            command = "true"
+
+            [[hooks]]
+            folder = "{path}"
+            event = "folder_down_sync_done"
+            command = "true"
+
+            [[hooks]]
+            folder_id = "unknown"
+            event = "folder_down_sync_done"
+            command = "true"
+            "#
+        ))
+        .unwrap();
+        let server_config = server_config(vec![syncthing::Folder {
+            id: "fid1".to_owned(),
+            label: "Photos".to_owned(),
+            path: dir.path().to_owned(),
+        }]);
+
+        let hooks_map = build_hooks_map(&hooks.hooks, &server_config);
+
+        assert_eq!(hooks_map.len(), 1);
+        let folder_hooks = &hooks_map[&(
+            config::FolderEvent::FolderDownSyncDone,
+            Rc::new(dir.path().try_into().unwrap()),
+        )];
+        assert_eq!(folder_hooks.len(), 3);
+    }
+
+    /// Devices must be selected by name, id or short id
+    #[test]
+    fn resolve_hook_devices() {
+        let server_config = server_config(Vec::new());
+
+        assert_eq!(
+            resolve_devices(
+                &[
+                    "scanner".to_owned(),
+                    "SCANNER-DEVICE2".to_owned(),
+                    "SCANNER".to_owned(),
+                    "LAPTOP1-DEVICE3".to_owned(),
+                ],
+                &server_config
+            ),
+            ["SCANNER", "SCANNER", "SCANNER", "LAPTOP1"]
+        );
+    }
+
+    /// A hook on a directory within a folder must be keyed by the folder, scoped to the directory
+    #[test]
+    fn resolve_hook_to_containing_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("docs/invoices")).unwrap();
+        let hooks: config::FolderConfig = toml::from_str(&format!(
+            r#"
+            [[hooks]]
+            folder = "{dir}/docs/invoices"
+            event = "file_down_sync_done"
+            command = "true"
+            "#,
+            dir = dir.path().to_str().unwrap()
+        ))
+        .unwrap();
+        let server_config = server_config(vec![syncthing::Folder {
+            id: "fid1".to_owned(),
+            label: "Folder".to_owned(),
+            path: dir.path().to_owned(),
+        }]);
+
+        let hooks_map = build_hooks_map(&hooks.hooks, &server_config);
+
+        let folder_hooks = &hooks_map[&(
+            config::FolderEvent::FileDownSyncDone,
+            Rc::new(dir.path().try_into().unwrap()),
+        )];
+        assert_eq!(folder_hooks.len(), 1);
+        let hook = &folder_hooks[0];
+        assert_eq!(
+            hook.subdir_path(Path::new("docs/invoices/2026/march.pdf")),
+            Some(Path::new("2026/march.pdf"))
+        );
+        assert!(hook.subdir_path(Path::new("docs/drafts/a.md")).is_none());
+        assert!(hook.subdir_path(Path::new("docs/invoices.pdf")).is_none());
+    }
+
+    /// A hook selecting several folders must be keyed by each of them
+    #[test]
+    fn resolve_hook_to_several_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let server_config = server_config(
+            ["a", "b", "c"]
+                .into_iter()
+                .map(|name| {
+                    let path = dir.path().join(name);
+                    fs::create_dir(&path).unwrap();
+                    syncthing::Folder {
+                        id: name.to_owned(),
+                        label: name.to_owned(),
+                        path,
+                    }
+                })
+                .collect(),
+        );
+        let hooks: config::FolderConfig = toml::from_str(&format!(
+            r#"
+            [[hooks]]
+            folder = "*"
+            event = "file_conflict"
+            command = "true"
+
+            [[hooks]]
+            folder = ["{dir}/a", "{dir}/[bc]"]
+            event = "file_down_sync_done"
+            command = "true"
+            "#,
+            dir = dir.path().to_str().unwrap()
+        ))
+        .unwrap();
+
+        let hooks_map = build_hooks_map(&hooks.hooks, &server_config);
+
+        assert_eq!(hooks_map.len(), 6);
+        for folder in &server_config.folders {
+            for event in [
+                config::FolderEvent::FileConflict,
+                config::FolderEvent::FileDownSyncDone,
+            ] {
+                let key = (event, Rc::new(folder.path.as_path().try_into().unwrap()));
+                assert_eq!(hooks_map[&key].len(), 1);
+            }
+        }
+    }
 }
@@ -0,0 +1,331 @@
+//! Capture of the output of hook commands
+
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Read as _, Write as _},
+    os::fd::{AsFd as _, OwnedFd},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+};
+
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+
+/// Maximum length of a captured line, beyond which it is split, to bound memory use
+const MAX_LINE_LEN: usize = 8 * 1024;
+
+/// Output stream of a command
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Stream {
+    /// Standard output
+    Stdout,
+    /// Standard error
+    Stderr,
+}
+
+impl Stream {
+    /// Name of the stream, as logged
+    fn name(self) -> &'static str {
+        match self {
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+        }
+    }
+}
+
+/// File the output of commands is appended to, rotated when it grows too big
+pub(crate) struct LogFile {
+    /// Path of the current file, rotated ones have a `.1`, `.2`... suffix, the oldest last
+    path: PathBuf,
+    /// Size beyond which the file is rotated
+    max_size: u64,
+    /// Number of rotated files to keep
+    count: usize,
+    /// Current file and its size, opened on first write
+    file: Option<(fs::File, u64)>,
+}
+
+impl LogFile {
+    /// Log file at `path`, rotated after `max_size` bytes, keeping `count` rotated files
+    pub(crate) fn new(path: PathBuf, max_size: u64, count: usize) -> Self {
+        Self {
+            path,
+            max_size,
+            count,
+            file: None,
+        }
+    }
+
+    /// Append a line, rotating the file first if it would grow beyond its maximum size
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        let (mut file, mut size) = match self.file.take() {
+            Some(file) => file,
+            None => self.open()?,
+        };
+        if size > 0 && size + len > self.max_size {
+            drop(file);
+            self.rotate()?;
+            (file, size) = self.open()?;
+        }
+        file.write_all(format!("{line}\n").as_bytes())?;
+        self.file = Some((file, size + len));
+        Ok(())
+    }
+
+    /// Open the current file for appending, with its size, creating its directory if needed
+    fn open(&self) -> io::Result<(fs::File, u64)> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let size = file.metadata()?.len();
+        Ok((file, size))
+    }
+
+    /// Shift the rotated files, dropping the oldest one, and rotate the current file
+    fn rotate(&self) -> io::Result<()> {
+        if self.count == 0 {
+            return fs::remove_file(&self.path);
+        }
+        for i in (1..self.count).rev() {
+            match fs::rename(self.rotated_path(i), self.rotated_path(i + 1)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))
+    }
+
+    /// Path of the rotated file with the given index, from 1
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(format!(".{index}"));
+        PathBuf::from(path)
+    }
+
+    /// Path of the current file
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Take the complete lines at the start of `pending`, without their line feed, splitting lines
+/// longer than `MAX_LINE_LEN` bytes
+fn take_lines(pending: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut lines = Vec::new();
+    loop {
+        if let Some(end) = pending.iter().take(MAX_LINE_LEN).position(|b| *b == b'\n') {
+            let mut line: Vec<u8> = pending.drain(..=end).collect();
+            line.pop();
+            lines.push(line);
+        } else if pending.len() >= MAX_LINE_LEN {
+            lines.push(pending.drain(..MAX_LINE_LEN).collect());
+        } else {
+            return lines;
+        }
+    }
+}
+
+/// Output stream of a process being read
+struct StreamReader {
+    /// Stream kind
+    stream: Stream,
+    /// Read end of the stream pipe
+    file: fs::File,
+    /// Data read after the last complete line
+    pending: Vec<u8>,
+}
+
+/// Output streams of the process of a hook, read by a single thread
+struct Capture {
+    /// Name of the hook
+    hook_name: String,
+    /// Process id
+    pid: u32,
+    /// Log file of the hook, if any
+    log_file: Option<Arc<Mutex<LogFile>>>,
+}
+
+impl Capture {
+    /// Read the streams until they are all closed
+    fn run(&self, mut readers: Vec<StreamReader>) {
+        let mut buf = [0; 8 * 1024];
+        while !readers.is_empty() {
+            let mut fds: Vec<_> = readers
+                .iter()
+                .map(|r| PollFd::new(r.file.as_fd(), PollFlags::POLLIN))
+                .collect();
+            match poll(&mut fds, PollTimeout::NONE) {
+                Ok(_) | Err(nix::errno::Errno::EINTR) => {}
+                Err(err) => {
+                    log::warn!("Failed to poll output of hook {:?}: {err}", self.hook_name);
+                    return;
+                }
+            }
+            let ready: Vec<bool> = fds
+                .iter()
+                .map(|fd| fd.revents().is_some_and(|r| !r.is_empty()))
+                .collect();
+            let mut ready = ready.into_iter();
+            readers
+                .retain_mut(|reader| !ready.next().unwrap_or(false) || self.read(reader, &mut buf));
+        }
+    }
+
+    /// Read what is available from a stream, logging its complete lines, returning false once it
+    /// is closed
+    fn read(&self, reader: &mut StreamReader, buf: &mut [u8]) -> bool {
+        let open = match reader.file.read(buf) {
+            Ok(0) => false,
+            Ok(len) => {
+                reader.pending.extend_from_slice(&buf[..len]);
+                true
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => true,
+            Err(err) => {
+                log::warn!(
+                    "Failed to read {} of hook {:?}: {err}",
+                    reader.stream.name(),
+                    self.hook_name
+                );
+                false
+            }
+        };
+        for line in take_lines(&mut reader.pending) {
+            self.log_line(reader.stream, &line);
+        }
+        if !open && !reader.pending.is_empty() {
+            self.log_line(reader.stream, &reader.pending);
+        }
+        open
+    }
+
+    /// Log a line of an output stream, and append it to the log file if any
+    fn log_line(&self, stream: Stream, line: &[u8]) {
+        let (hook_name, pid) = (&self.hook_name, self.pid);
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches('\r');
+        match stream {
+            Stream::Stdout => log::info!("Hook {hook_name:?} [{pid}] stdout: {line}"),
+            Stream::Stderr => log::warn!("Hook {hook_name:?} [{pid}] stderr: {line}"),
+        }
+        if let Some(log_file) = &self.log_file {
+            let mut log_file = log_file.lock().unwrap_or_else(PoisonError::into_inner);
+            let entry = format!(
+                "{time} [{pid}] {stream}: {line}",
+                time = jiff::Timestamp::now(),
+                stream = stream.name()
+            );
+            if let Err(err) = log_file.write_line(&entry) {
+                log::error!(
+                    "Failed to write to log file {:?} of hook {hook_name:?}: {err}",
+                    log_file.path()
+                );
+            }
+        }
+    }
+}
+
+/// Log the lines of the output streams of the process `pid` of a hook, and append them to its
+/// log file if any, from a single other thread until they are all closed
+pub(crate) fn capture(
+    outputs: Vec<(Stream, OwnedFd)>,
+    hook_name: String,
+    pid: u32,
+    log_file: Option<Arc<Mutex<LogFile>>>,
+) {
+    let readers = outputs
+        .into_iter()
+        .map(|(stream, fd)| StreamReader {
+            stream,
+            file: fs::File::from(fd),
+            pending: Vec::new(),
+        })
+        .collect();
+    let capture = Capture {
+        hook_name,
+        pid,
+        log_file,
+    };
+    thread::spawn(move || capture.run(readers));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The file must be rotated when it would grow beyond its maximum size, keeping the
+    /// configured number of rotated files
+    #[test]
+    fn rotate_log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hook.log");
+        let mut log_file = LogFile::new(path.clone(), 8, 2);
+
+        for line in ["one", "two", "three", "four", "five"] {
+            log_file.write_line(line).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "five\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("hook.log.1")).unwrap(),
+            "four\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("hook.log.2")).unwrap(),
+            "three\n"
+        );
+        assert!(!dir.path().join("hook.log.3").exists());
+    }
+
+    /// The directory of the file must be created if missing
+    #[test]
+    fn create_log_file_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state/stfed/hook.log");
+        let mut log_file = LogFile::new(path.clone(), 1024, 1);
+
+        log_file.write_line("line").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line\n");
+    }
+
+    /// Lines must be split when too long, to not buffer unbounded output
+    #[test]
+    fn split_long_lines() {
+        let long = "a".repeat(10_000);
+        let (head, tail) = long.split_at(8192);
+        let mut pending = format!("short\n\n{long}\nend").into_bytes();
+
+        let lines = take_lines(&mut pending);
+
+        assert_eq!(
+            lines,
+            ["short", "", head, tail].map(|l| l.as_bytes().to_vec())
+        );
+        assert_eq!(pending, b"end");
+    }
+
+    /// Writes must go on after an existing file, taking its size into account for rotation
+    #[test]
+    fn append_to_existing_log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hook.log");
+        fs::write(&path, "previous\n").unwrap();
+        let mut log_file = LogFile::new(path.clone(), 12, 1);
+
+        log_file.write_line("next").unwrap();
+        log_file.write_line("last").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "next\nlast\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("hook.log.1")).unwrap(),
+            "previous\n"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Time windows hooks are allowed to run in
+
+use std::str::FromStr;
+
+use jiff::civil;
+
+/// Error when parsing a time window
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum Error {
+    /// Daily range with an invalid time
+    #[error("Invalid time {0:?}, expected HH:MM")]
+    InvalidTime(String),
+    /// Daily range starting and ending at the same time
+    #[error("Empty time range")]
+    EmptyRange,
+    /// Cron expression without the 5 expected fields
+    #[error("Expected 5 cron fields (minute hour day-of-month month day-of-week), got {0}")]
+    FieldCount(usize),
+    /// Cron field with an invalid value
+    #[error("Invalid cron field {0:?}")]
+    InvalidField(String),
+}
+
+/// Time window during which a hook can run, in local time
+#[derive(Debug)]
+pub(crate) enum Window {
+    /// Daily time range, wrapping around midnight if it ends before it starts
+    Daily {
+        /// Time the window opens at
+        start: civil::Time,
+        /// Time the window closes at, excluded
+        end: civil::Time,
+    },
+    /// Cron-like expression, the window being the minutes it matches
+    Cron(Cron),
+}
+
+impl Window {
+    /// Whether a local date and time is within the window
+    pub(crate) fn contains(&self, dt: civil::DateTime) -> bool {
+        match self {
+            Self::Daily { start, end } => {
+                let time = dt.time();
+                if start < end {
+                    (*start..*end).contains(&time)
+                } else {
+                    (time >= *start) || (time < *end)
+                }
+            }
+            Self::Cron(cron) => cron.matches(dt),
+        }
+    }
+}
+
+impl FromStr for Window {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((start, end)) = s.split_once('-').filter(|_| s.contains(':')) {
+            let parse_time = |t: &str| {
+                civil::Time::strptime("%H:%M", t.trim())
+                    .map_err(|_| Error::InvalidTime(t.trim().to_owned()))
+            };
+            let (start, end) = (parse_time(start)?, parse_time(end)?);
+            if start == end {
+                return Err(Error::EmptyRange);
+            }
+            Ok(Self::Daily { start, end })
+        } else {
+            s.parse().map(Self::Cron)
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Window {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|err| serde::de::Error::custom(format!("Invalid schedule {s:?}: {err}")))
+    }
+}
+
+/// Whether a local date and time is within any of the windows, or there are none
+pub(crate) fn is_open(windows: &[Window], dt: civil::DateTime) -> bool {
+    windows.is_empty() || windows.iter().any(|w| w.contains(dt))
+}
+
+/// Cron fields, as bit sets of the allowed values
+#[derive(Debug)]
+pub(crate) struct Cron {
+    /// Minutes, 0-59
+    minutes: u64,
+    /// Hours, 0-23
+    hours: u64,
+    /// Days of month, 1-31
+    days_of_month: u64,
+    /// Months, 1-12
+    months: u64,
+    /// Days of week, 0-6 from Sunday
+    days_of_week: u64,
+    /// Whether the days of month field is restricted, not `*`
+    days_of_month_restricted: bool,
+    /// Whether the days of week field is restricted, not `*`
+    days_of_week_restricted: bool,
+}
+
+impl Cron {
+    /// Whether a local date and time matches
+    fn matches(&self, dt: civil::DateTime) -> bool {
+        let is_set = |set: u64, val: i8| (set >> val) & 1 == 1;
+        let day_of_month = is_set(self.days_of_month, dt.day());
+        let day_of_week = is_set(self.days_of_week, dt.weekday().to_sunday_zero_offset());
+        // Like cron, if both day fields are restricted, matching any of them is enough
+        let day = if self.days_of_month_restricted && self.days_of_week_restricted {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        };
+        is_set(self.minutes, dt.minute())
+            && is_set(self.hours, dt.hour())
+            && is_set(self.months, dt.month())
+            && day
+    }
+}
+
+/// Parse cron field of comma separated `*`, `N`, or `N-M` items, each with an optional `/STEP`,
+/// into a bit set of values in `min..=max`
+fn parse_field(field: &str, min: u8, max: u8) -> Result<u64, Error> {
+    let invalid = || Error::InvalidField(field.to_owned());
+    let mut set = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u8>().map_err(|_| invalid())?),
+            None => (item, 1_u8),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                start.parse().map_err(|_| invalid())?,
+                end.parse().map_err(|_| invalid())?,
+            )
+        } else {
+            let val = range.parse().map_err(|_| invalid())?;
+            // Like cron, a single value with a step is the start of a range
+            (val, if item.contains('/') { max } else { val })
+        };
+        if (step == 0) || (start < min) || (end > max) || (start > end) {
+            return Err(invalid());
+        }
+        for val in (start..=end).step_by(step.into()) {
+            set |= 1 << val;
+        }
+    }
+    Ok(set)
+}
+
+impl FromStr for Cron {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<_> = s.split_whitespace().collect();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(Error::FieldCount(fields.len()));
+        };
+        let mut days_of_week_set = parse_field(days_of_week, 0, 7)?;
+        // Sunday is either 0 or 7
+        if days_of_week_set & (1 << 7) != 0 {
+            days_of_week_set |= 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days_of_month: parse_field(days_of_month, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            days_of_week: days_of_week_set,
+            days_of_month_restricted: days_of_month != "*",
+            days_of_week_restricted: days_of_week != "*",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Local date and time from a `YYYY-MM-DD HH:MM` string
+    fn dt(s: &str) -> civil::DateTime {
+        civil::DateTime::strptime("%Y-%m-%d %H:%M", s).unwrap()
+    }
+
+    /// Daily ranges must contain their start but not their end, wrapping around midnight
+    #[test]
+    fn daily_window() {
+        let night: Window = "22:00-06:00".parse().unwrap();
+        assert!(night.contains(dt("2026-10-18 22:00")));
+        assert!(night.contains(dt("2026-10-18 23:59")));
+        assert!(night.contains(dt("2026-10-19 05:59")));
+        assert!(!night.contains(dt("2026-10-19 06:00")));
+        assert!(!night.contains(dt("2026-10-19 12:00")));
+
+        let lunch: Window = "12:00 - 13:30".parse().unwrap();
+        assert!(lunch.contains(dt("2026-10-18 13:29")));
+        assert!(!lunch.contains(dt("2026-10-18 13:30")));
+        assert!(!lunch.contains(dt("2026-10-18 11:59")));
+    }
+
+    /// Cron windows must match the minutes of their expression
+    #[test]
+    fn cron_window() {
+        // 2026-10-18 is a Sunday
+        let weekend_nights: Window = "* 0-5 * * 6,7".parse().unwrap();
+        assert!(weekend_nights.contains(dt("2026-10-18 03:17")));
+        assert!(weekend_nights.contains(dt("2026-10-17 00:00")));
+        assert!(!weekend_nights.contains(dt("2026-10-19 03:17")));
+        assert!(!weekend_nights.contains(dt("2026-10-18 06:00")));
+
+        let quarters: Window = "*/15 * * * *".parse().unwrap();
+        assert!(quarters.contains(dt("2026-10-18 10:45")));
+        assert!(!quarters.contains(dt("2026-10-18 10:46")));
+
+        // Day of month or day of week, like cron
+        let first_or_monday: Window = "* * 1 * 1".parse().unwrap();
+        assert!(first_or_monday.contains(dt("2026-11-01 10:00")));
+        assert!(first_or_monday.contains(dt("2026-10-19 10:00")));
+        assert!(!first_or_monday.contains(dt("2026-10-20 10:00")));
+    }
+
+    /// Invalid windows must be rejected
+    #[test]
+    fn reject_invalid_windows() {
+        for s in [
+            "",
+            "22:00",
+            "22:00-25:00",
+            "10:00-10:00",
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * jan *",
+        ] {
+            assert!(s.parse::<Window>().is_err(), "{s:?}");
+        }
+    }
+}
@@ -3,7 +3,8 @@
 use std::{
     collections::{HashMap, VecDeque},
     io,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
     time::Duration,
 };
 
@@ -36,19 +37,98 @@ pub(crate) struct Client {
     session: ureq::Agent,
     /// Start time of the server, changing each time it restarts
     start_time: String,
+    /// Configuration of the server, as last fetched
+    server_config: Mutex<Option<Arc<ServerConfig>>>,
 }
 
+/// Folder configured on the server
+#[derive(Debug)]
+pub(crate) struct Folder {
+    /// Folder id
+    pub id: String,
+    /// Folder label, not necessarily unique
+    pub label: String,
+    /// Local path of the folder
+    pub path: PathBuf,
+}
+
+/// Device configured on the server
+#[derive(Debug)]
+pub(crate) struct Device {
+    /// Device id
+    pub id: String,
+    /// Device name
+    pub name: String,
+}
+
+/// Short form of a device id, as the server reports the device that modified a file
+pub(crate) fn short_device_id(id: &str) -> &str {
+    id.split('-').next().unwrap_or(id)
+}
+
+/// Configuration of the server
+#[derive(Debug)]
+pub(crate) struct ServerConfig {
+    /// Configured folders
+    pub folders: Vec<Folder>,
+    /// Configured remote devices, and local one
+    pub devices: Vec<Device>,
+}
+
+impl ServerConfig {
+    /// Folder with the given id, if configured
+    pub(crate) fn folder(&self, id: &str) -> Option<&Folder> {
+        self.folders.iter().find(|f| f.id == id)
+    }
+
+    /// Device with the given full or short id, if configured
+    pub(crate) fn device(&self, id: &str) -> Option<&Device> {
+        let short_id = short_device_id(id);
+        self.devices
+            .iter()
+            .find(|d| short_device_id(&d.id) == short_id)
+    }
+}
+
+/// Information of a file, in the global state of its folder
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) struct FileInfo {
+    /// Size in bytes
+    pub size: u64,
+    /// Modification time, in RFC 3339 format
+    pub modified: String,
+    /// Permissions, in octal
+    pub permissions: String,
+    /// Version vector, as `device:counter` entries
+    pub version: Vec<String>,
+    /// Short id of the device that last modified the file
+    pub modified_by: String,
+    /// Hash of the block hash list, if the file has blocks
+    pub blocks_hash: Option<String>,
+}
+
+/// Files synced down, by folder id and path, waiting for the server to report the device that
+/// changed them
+type SyncedFiles = HashMap<(String, String), ServerEvent>;
+
 /// Position in the event stream of a server instance
-#[cfg_attr(test, derive(Debug, Eq, PartialEq))]
 pub(crate) struct Cursor {
     /// Start time of the server instance the event ids refer to
     server_start_time: String,
     /// Id of the last consumed event
     last_id: u64,
+    /// Files synced down before the last consumed event, whose device is not reported yet
+    synced_files: SyncedFiles,
 }
 
 /// API timeout for long event requests
 const REST_TIMEOUT_EVENT_STREAM: Duration = Duration::from_secs(60 * 60);
+/// Maximum number of events between a file sync and the report of the device that changed it,
+/// after which the server is assumed to never report it
+///
+/// The server reports the changes when it commits them to its database, by batches of at most
+/// 1000 files.
+const MAX_SYNCED_FILE_REPORT_LAG: u64 = 10_000;
 /// HTTP timeout for normal requests
 const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
 /// Header key value for Synthing API key
@@ -58,11 +138,14 @@ const HEADER_API_KEY: &str = "X-API-Key";
 // Notes:
 // DownloadProgress is not emitted for small downloads
 // FolderCompletion is for remote device progress
+// RemoteChangeDetected is emitted after the ItemFinished of the same file, when the change is
+// committed, and is the only one with the device that made the change
 const EVENT_TYPES: &[&str] = &[
     "ItemFinished",
     "FolderSummary",
     "LocalChangeDetected",
     "ConfigSaved",
+    "RemoteChangeDetected",
 ];
 
 impl Client {
@@ -93,21 +176,82 @@ impl Client {
             session,
             api_key: cfg.api_key.clone(),
             start_time: system_status.start_time,
+            server_config: Mutex::new(None),
         })
     }
 
-    /// Build the folder id to local path map, from the configuration the server currently has
-    fn folder_map(&self) -> anyhow::Result<HashMap<String, PathBuf>> {
+    /// Get the configuration of the server, fetched on first use and then cached until fetched
+    /// again
+    pub(crate) fn server_config(&self) -> anyhow::Result<Arc<ServerConfig>> {
+        match self.cached_server_config() {
+            Some(server_config) => Ok(server_config),
+            None => self.fetch_server_config(),
+        }
+    }
+
+    /// Configuration of the server, as last fetched, if any
+    fn cached_server_config(&self) -> Option<Arc<ServerConfig>> {
+        self.server_config
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Fetch the configuration the server currently has, and cache it
+    fn fetch_server_config(&self) -> anyhow::Result<Arc<ServerConfig>> {
+        let server_config = Arc::new(self.request_server_config()?);
+        *self
+            .server_config
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Arc::clone(&server_config));
+        Ok(server_config)
+    }
+
+    /// Request the configuration the server currently has
+    fn request_server_config(&self) -> anyhow::Result<ServerConfig> {
         let system_config: syncthing_rest::SystemConfig = serde_json::from_str(&Self::get(
             &self.session,
             &self.base_url.join("rest/system/config")?,
             &self.api_key,
         )?)?;
-        Ok(system_config
-            .folders
-            .into_iter()
-            .map(|f| (f.id, PathBuf::from(f.path)))
-            .collect())
+        Ok(ServerConfig {
+            folders: system_config
+                .folders
+                .into_iter()
+                .map(|f| Folder {
+                    id: f.id,
+                    label: f.label,
+                    path: PathBuf::from(f.path),
+                })
+                .collect(),
+            devices: system_config
+                .devices
+                .into_iter()
+                .map(|d| Device {
+                    id: d.device_id,
+                    name: d.name,
+                })
+                .collect(),
+        })
+    }
+
+    /// Get the information of a file, according to the global state of its folder
+    pub(crate) fn file_info(&self, folder_id: &str, path: &Path) -> anyhow::Result<FileInfo> {
+        let mut url = self.base_url.join("rest/db/file")?;
+        url.query_pairs_mut()
+            .append_pair("folder", folder_id)
+            .append_pair("file", &path.to_string_lossy());
+        let file: syncthing_rest::DbFile =
+            serde_json::from_str(&Self::get(&self.session, &url, &self.api_key)?)?;
+        let info = file.global;
+        Ok(FileInfo {
+            size: info.size,
+            modified: info.modified,
+            permissions: info.permissions,
+            version: info.version,
+            modified_by: info.modified_by,
+            blocks_hash: info.blocks_hash,
+        })
     }
 
     /// Send a request to an endpoint, and return the response body
@@ -127,20 +271,18 @@ impl Client {
     }
 
     /// Iterator over infinite stream of events, resuming from `cursor` if set
-    pub(crate) fn iter_events(&self, cursor: Option<&Cursor>) -> FolderEventIterator<'_> {
-        FolderEventIterator::new(self, self.resume_id(cursor))
-    }
-
-    /// Event id to resume the stream from, `None` to start after the events already buffered
-    fn resume_id(&self, cursor: Option<&Cursor>) -> Option<u64> {
-        let cursor = cursor?;
-        if cursor.server_start_time == self.start_time {
-            Some(cursor.last_id)
-        } else {
+    pub(crate) fn iter_events(&self, cursor: Option<Cursor>) -> FolderEventIterator<'_> {
+        let (resume_id, synced_files) = match cursor {
+            Some(cursor) if cursor.server_start_time == self.start_time => {
+                (Some(cursor.last_id), cursor.synced_files)
+            }
             // The server numbers the events of a subscription from scratch when it restarts, so
             // the ids of a previous instance are meaningless: process its whole event buffer
-            Some(0)
-        }
+            Some(_) => (Some(0), SyncedFiles::new()),
+            // Start after the events already buffered
+            None => (None, SyncedFiles::new()),
+        };
+        FolderEventIterator::new(self, resume_id, synced_files)
     }
 
     /// Send an events request, and return the events it yielded, if any
@@ -201,25 +343,25 @@ pub(crate) struct FolderEventIterator<'a> {
     client: &'a Client,
     /// Id of the last consumed event, `None` until the cursor has been primed
     last_id: Option<u64>,
-    /// Folder id to local path, empty until fetched from the server
-    folder_map: HashMap<String, PathBuf>,
-    /// Whether the folder map was fetched for the events of the current batch
-    folder_map_fetched: bool,
+    /// Whether the server configuration was fetched for the events of the current batch
+    server_config_fetched: bool,
     /// Events received from the server, not yet consumed
     pending: VecDeque<syncthing_rest::Event>,
+    /// Files synced down, waiting for the device that changed them
+    synced_files: SyncedFiles,
     /// Last state change for folder to avoid duplicates
     folder_state_change_time: HashMap<String, String>,
 }
 
 impl<'a> FolderEventIterator<'a> {
     /// Constructor
-    fn new(client: &'a Client, resume_id: Option<u64>) -> Self {
+    fn new(client: &'a Client, resume_id: Option<u64>, synced_files: SyncedFiles) -> Self {
         Self {
             client,
             last_id: resume_id,
-            folder_map: HashMap::new(),
-            folder_map_fetched: false,
+            server_config_fetched: false,
             pending: VecDeque::new(),
+            synced_files,
             folder_state_change_time: HashMap::new(),
         }
     }
@@ -229,23 +371,76 @@ impl<'a> FolderEventIterator<'a> {
         self.last_id.map(|last_id| Cursor {
             server_start_time: self.client.start_time.clone(),
             last_id,
+            synced_files: self.synced_files.clone(),
         })
     }
 
+    /// Wait for the device that changed a file synced down by the event `source`
+    fn add_synced_file(&mut self, folder: String, path: String, source: ServerEvent) {
+        let id = source.id;
+        self.synced_files
+            .retain(|(synced_folder, synced_path), synced| {
+                let reported = synced.id + MAX_SYNCED_FILE_REPORT_LAG >= id;
+                if !reported {
+                    log::warn!(
+                        "Ignoring sync of {synced_path:?} in folder {synced_folder:?}, never committed"
+                    );
+                }
+                reported
+            });
+        self.synced_files.insert((folder, path), source);
+    }
+
+    /// Event of a file synced down, once the server committed the `change` of it, `None` if the
+    /// change was not synced down, or is for an unknown folder
+    fn committed_file(
+        &mut self,
+        change: syncthing_rest::RemoteChangeDetectedEvent,
+    ) -> Option<anyhow::Result<(Event, ServerEvent)>> {
+        // Deletions, metadata changes, and failed syncs have no synced file
+        let source = self
+            .synced_files
+            .remove(&(change.folder_id.clone(), change.path.clone()))?;
+        let folder_path = match self.folder_path(&change.folder_id) {
+            Ok(Some(folder_path)) => folder_path,
+            Ok(None) => {
+                log::warn!(
+                    "Ignoring event of unknown folder id {folder:?}",
+                    folder = change.folder_id
+                );
+                return None;
+            }
+            Err(err) => return Some(Err(err)),
+        };
+        Some(Ok((
+            Event::FileDownSyncDone {
+                path: PathBuf::from(change.path),
+                folder: folder_path,
+                modified_by: change.modified_by,
+            },
+            source,
+        )))
+    }
+
     /// Local path of the folder with id `folder`, `None` if the server does not configure it
     fn folder_path(&mut self, folder: &str) -> anyhow::Result<Option<PathBuf>> {
         // The server applies a configuration change several seconds before it reports it with a
         // ConfigSaved event, so a folder it configured since the last fetch is missing here
-        if !self.folder_map.contains_key(folder) && !self.folder_map_fetched {
-            self.folder_map = self.client.folder_map()?;
-            self.folder_map_fetched = true;
+        let mut server_config = self.client.cached_server_config();
+        if server_config
+            .as_ref()
+            .is_none_or(|c| c.folder(folder).is_none())
+            && !self.server_config_fetched
+        {
+            server_config = Some(self.client.fetch_server_config()?);
+            self.server_config_fetched = true;
         }
-        Ok(self.folder_map.get(folder).cloned())
+        Ok(server_config.and_then(|c| c.folder(folder).map(|f| f.path.clone())))
     }
 }
 
 impl Iterator for FolderEventIterator<'_> {
-    type Item = anyhow::Result<Event>;
+    type Item = anyhow::Result<(Event, ServerEvent)>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
@@ -267,14 +462,15 @@ impl Iterator for FolderEventIterator<'_> {
                     Err(err) => return Some(Err(err)),
                 }
                 // The server may have configured new folders since the previous batch
-                self.folder_map_fetched = false;
+                self.server_config_fetched = false;
                 continue;
             };
 
             // Update last id
             self.last_id = Some(new_evt.id);
 
-            return match new_evt.data {
+            let (data, source) = ServerEvent::split(new_evt);
+            return match data {
                 // The server emits this event for each item the sync processed, whatever the
                 // outcome: a failed sync left no usable file, and a deletion or a metadata
                 // change synced no content
@@ -285,18 +481,17 @@ impl Iterator for FolderEventIterator<'_> {
                     item_type,
                     action: syncthing_rest::ItemAction::Update,
                 }) if item_type == "file" => {
-                    let folder_path = match self.folder_path(&folder) {
-                        Ok(Some(folder_path)) => folder_path,
-                        Ok(None) => {
-                            log::warn!("Ignoring event of unknown folder id {folder:?}");
-                            continue;
-                        }
-                        Err(err) => return Some(Err(err)),
-                    };
-                    Some(Ok(Event::FileDownSyncDone {
-                        path: PathBuf::from(item),
-                        folder: folder_path,
-                    }))
+                    self.add_synced_file(folder, item, source);
+                    continue;
+                }
+                syncthing_rest::EventData::RemoteChangeDetected(evt_data)
+                    if evt_data.item_type == "file" =>
+                {
+                    if let Some(event) = self.committed_file(evt_data) {
+                        Some(event)
+                    } else {
+                        continue;
+                    }
                 }
                 syncthing_rest::EventData::FolderSummary(evt_data) => {
                     if evt_data.summary.need_total_items > 0 {
@@ -321,9 +516,12 @@ impl Iterator for FolderEventIterator<'_> {
                     }
                     self.folder_state_change_time
                         .insert(evt_data.folder, changed);
-                    Some(Ok(Event::FolderDownSyncDone {
-                        folder: folder_path,
-                    }))
+                    Some(Ok((
+                        Event::FolderDownSyncDone {
+                            folder: folder_path,
+                        },
+                        source,
+                    )))
                 }
                 // see https://github.com/syncthing/syncthing/issues/6121#issuecomment-549077477
                 syncthing_rest::EventData::LocalChangeDetected(evt_data)
@@ -342,10 +540,13 @@ impl Iterator for FolderEventIterator<'_> {
                         }
                         Err(err) => return Some(Err(err)),
                     };
-                    Some(Ok(Event::FileConflict {
-                        path: PathBuf::from(evt_data.path),
-                        folder: folder_path,
-                    }))
+                    Some(Ok((
+                        Event::FileConflict {
+                            path: PathBuf::from(evt_data.path),
+                            folder: folder_path,
+                        },
+                        source,
+                    )))
                 }
                 syncthing_rest::EventData::ConfigSaved(_) => {
                     Some(Err(ServerConfigChanged::ConfigSaved.into()))
@@ -366,6 +567,8 @@ pub(crate) enum Event {
         path: PathBuf,
         /// Local path of the folder
         folder: PathBuf,
+        /// Short id of the device that changed the file
+        modified_by: String,
     },
     /// See `config::FolderEvent::FolderDownSyncDone`
     FolderDownSyncDone {
@@ -381,6 +584,52 @@ pub(crate) enum Event {
     },
 }
 
+/// Server event a Syncthing event comes from
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) struct ServerEvent {
+    /// Id of the event, in the subscription of the client
+    pub id: u64,
+    /// Id of the event, across all subscriptions
+    pub global_id: u64,
+    /// Server event type
+    pub event_type: syncthing_rest::EventType,
+    /// Time of the event, in RFC 3339 format
+    pub time: String,
+    /// Payload of the event, as sent by the server
+    pub data: Box<serde_json::value::RawValue>,
+}
+
+impl ServerEvent {
+    /// String value of the payload at a JSON pointer, if any
+    pub(crate) fn data_str(&self, pointer: &str) -> Option<String> {
+        serde_json::from_str::<serde_json::Value>(self.data.get())
+            .ok()?
+            .pointer(pointer)?
+            .as_str()
+            .map(str::to_owned)
+    }
+
+    /// Split a server event into its parsed payload, and the rest of it
+    fn split(event: syncthing_rest::Event) -> (syncthing_rest::EventData, Self) {
+        let syncthing_rest::Event {
+            id,
+            global_id,
+            kind,
+            time,
+            data,
+            raw_data,
+        } = event;
+        let source = Self {
+            id,
+            global_id,
+            event_type: kind,
+            time,
+            data: raw_data,
+        };
+        (data, source)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -410,15 +659,30 @@ mod tests {
     /// Id of the folder the tests sync
     const FOLDER_ID: &str = "fid1";
 
+    /// Label of the folder the tests sync
+    const FOLDER_LABEL: &str = "Folder";
+
     /// Local path of the folder the tests sync
     const FOLDER_PATH: &str = "/data/folder";
 
+    /// Id of the local device
+    const LOCAL_DEVICE_ID: &str = "TESTDEV-ICEID";
+
+    /// Id of the remote device
+    const REMOTE_DEVICE_ID: &str = "REMOTE1-DEVICE2";
+
+    /// Name of the remote device
+    const REMOTE_DEVICE_NAME: &str = "scanner";
+
     /// Id of a folder absent from the server configuration
     const UNKNOWN_FOLDER_ID: &str = "fid2";
 
     /// Id of a folder added to the server configuration while connected
     const ADDED_FOLDER_ID: &str = "fid3";
 
+    /// Label of the folder added to the server configuration while connected
+    const ADDED_FOLDER_LABEL: &str = "Added";
+
     /// Local path of the folder added to the server configuration while connected
     const ADDED_FOLDER_PATH: &str = "/data/added";
 
@@ -433,14 +697,16 @@ mod tests {
     /// Server state, shared with the request handling threads
     #[derive(Default)]
     struct State {
-        /// Local path of each configured folder, by folder id
-        folders: Vec<(String, String)>,
+        /// Label and local path of each configured folder, by folder id
+        folders: Vec<(String, String, String)>,
         /// Emitted events, in chronological order
         events: Vec<BufferedEvent>,
         /// Query string of each events request received, in order of reception
         event_requests: Vec<String>,
         /// Number of system configuration requests received
         config_requests: usize,
+        /// Short id of the device that last modified each file, by folder id and file path
+        modified_by: HashMap<(String, String), String>,
     }
 
     impl State {
@@ -491,8 +757,8 @@ mod tests {
     }
 
     impl TestSyncthingServer {
-        /// Start a server exposing the given folders, as `(id, path)` pairs
-        fn start(folders: &[(&str, &str)]) -> Self {
+        /// Start a server exposing the given folders, as `(id, label, path)` tuples
+        fn start(folders: &[(&str, &str, &str)]) -> Self {
             let server = Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
             let addr = server.server_addr().to_ip().unwrap();
             let url = url::Url::parse(&format!("http://{addr}/")).unwrap();
@@ -500,7 +766,9 @@ mod tests {
                 Mutex::new(State {
                     folders: folders
                         .iter()
-                        .map(|(id, path)| ((*id).to_owned(), (*path).to_owned()))
+                        .map(|(id, label, path)| {
+                            ((*id).to_owned(), (*label).to_owned(), (*path).to_owned())
+                        })
                         .collect(),
                     ..State::default()
                 }),
@@ -567,13 +835,23 @@ mod tests {
         }
 
         /// Add a folder to the configuration, as the server does before reporting the change
-        fn add_folder(&self, id: &str, path: &str) {
+        fn add_folder(&self, id: &str, label: &str, path: &str) {
             let (state, _state_changed) = &*self.state;
             state
                 .lock()
                 .unwrap()
                 .folders
-                .push((id.to_owned(), path.to_owned()));
+                .push((id.to_owned(), label.to_owned(), path.to_owned()));
+        }
+
+        /// Set the device that last modified a file
+        fn set_modified_by(&self, folder: &str, file: &str, device: &str) {
+            let (state, _state_changed) = &*self.state;
+            state
+                .lock()
+                .unwrap()
+                .modified_by
+                .insert((folder.to_owned(), file.to_owned()), device.to_owned());
         }
 
         /// Number of system configuration requests received so far
@@ -638,11 +916,12 @@ mod tests {
         let body = match url.path() {
             "/rest/system/config" => system_config(state),
             "/rest/system/status" => json!({
-                "myID": "TESTDEV-ICEID",
+                "myID": LOCAL_DEVICE_ID,
                 "startTime": SERVER_START_TIME,
             })
             .to_string(),
             "/rest/events" => events(state, &url),
+            "/rest/db/file" => db_file(state, &url),
             path => panic!("Unexpected request path {path:?}"),
         };
         let content_type =
@@ -661,8 +940,34 @@ mod tests {
             "folders": state
                 .folders
                 .iter()
-                .map(|(id, path)| json!({"id": id, "path": path}))
+                .map(|(id, label, path)| json!({"id": id, "label": label, "path": path}))
                 .collect::<Vec<_>>(),
+            "devices": [
+                {"deviceID": LOCAL_DEVICE_ID, "name": "local"},
+                {"deviceID": REMOTE_DEVICE_ID, "name": REMOTE_DEVICE_NAME},
+            ],
+        })
+        .to_string()
+    }
+
+    /// Serve a file information request
+    fn db_file(state: &(Mutex<State>, Condvar), url: &url::Url) -> String {
+        let query: HashMap<_, _> = url.query_pairs().collect();
+        let key = (query["folder"].to_string(), query["file"].to_string());
+        let (state, _state_changed) = state;
+        json!({
+            "global": {
+                "name": key.1,
+                "type": "FILE_INFO_TYPE_FILE",
+                "size": 1234,
+                "modified": "2026-07-11T12:30:00.5+02:00",
+                "deleted": false,
+                "permissions": "0644",
+                "version": ["REMOTE1:1752229800", "LOCAL1:1752229000"],
+                "sequence": 42,
+                "modifiedBy": state.lock().unwrap().modified_by[&key],
+                "blocksHash": "q83vEjRWeJA=",
+            },
         })
         .to_string()
     }
@@ -726,6 +1031,38 @@ mod tests {
         item_finished_data(item, folder, None, "file", "update")
     }
 
+    /// Data payload of a `RemoteChangeDetected` event, for a file changed by the remote device
+    fn remote_change(path: &str, folder: &str, action: &str) -> serde_json::Value {
+        json!({
+            "action": action,
+            "folder": folder,
+            "folderID": folder,
+            "label": "Folder",
+            "path": path,
+            "type": "file",
+            "modifiedBy": short_device_id(REMOTE_DEVICE_ID),
+        })
+    }
+
+    /// Events of a file successfully updated by a sync, and then committed
+    fn file_synced(item: &str, folder: &str) -> [(&'static str, serde_json::Value); 2] {
+        [
+            ("ItemFinished", item_finished(item, folder)),
+            (
+                "RemoteChangeDetected",
+                remote_change(item, folder, "modified"),
+            ),
+        ]
+    }
+
+    /// Events of files successfully updated by a sync, and then committed
+    fn files_synced(items: &[&str]) -> Vec<(&'static str, serde_json::Value)> {
+        items
+            .iter()
+            .flat_map(|item| file_synced(item, FOLDER_ID))
+            .collect()
+    }
+
     /// Data payload of a `FolderSummary` event
     fn folder_summary(
         folder: &str,
@@ -780,10 +1117,10 @@ mod tests {
     fn stream_events(
         client: Client,
         cursor: Option<Cursor>,
-    ) -> mpsc::Receiver<anyhow::Result<Event>> {
+    ) -> mpsc::Receiver<anyhow::Result<(Event, ServerEvent)>> {
         let (event_tx, event_rx) = mpsc::channel();
         thread::spawn(move || {
-            for event in client.iter_events(cursor.as_ref()) {
+            for event in client.iter_events(cursor) {
                 if event_tx.send(event).is_err() {
                     break;
                 }
@@ -798,6 +1135,7 @@ mod tests {
         Cursor {
             server_start_time: server_start_time.to_owned(),
             last_id,
+            synced_files: SyncedFiles::new(),
         }
     }
 
@@ -806,13 +1144,17 @@ mod tests {
         let cfg = config::Config {
             url,
             api_key: "apikey".to_owned(),
+            max_concurrent_hooks: None,
         };
         Client::new(&cfg).unwrap()
     }
 
     /// Consume `count` events of the stream
-    fn recv_events(events: &mpsc::Receiver<anyhow::Result<Event>>, count: usize) -> Vec<Event> {
-        iter::repeat_with(|| events.recv_timeout(EVENT_DELAY).unwrap().unwrap())
+    fn recv_events(
+        events: &mpsc::Receiver<anyhow::Result<(Event, ServerEvent)>>,
+        count: usize,
+    ) -> Vec<Event> {
+        iter::repeat_with(|| events.recv_timeout(EVENT_DELAY).unwrap().unwrap().0)
             .take(count)
             .collect()
     }
@@ -822,6 +1164,7 @@ mod tests {
         Event::FileDownSyncDone {
             path: PathBuf::from(item),
             folder: PathBuf::from(FOLDER_PATH),
+            modified_by: short_device_id(REMOTE_DEVICE_ID).to_owned(),
         }
     }
 
@@ -832,18 +1175,95 @@ mod tests {
         }
     }
 
+    /// The configured folders and devices must be reported
+    #[test]
+    fn get_server_config() {
+        let server = TestSyncthingServer::start(&[
+            (FOLDER_ID, FOLDER_LABEL, FOLDER_PATH),
+            (ADDED_FOLDER_ID, ADDED_FOLDER_LABEL, ADDED_FOLDER_PATH),
+        ]);
+
+        let server_config = connect(server.url()).server_config().unwrap();
+
+        assert_eq!(
+            server_config
+                .folders
+                .iter()
+                .map(|f| (f.id.as_str(), f.label.as_str(), f.path.to_str().unwrap()))
+                .collect::<Vec<_>>(),
+            [
+                (FOLDER_ID, FOLDER_LABEL, FOLDER_PATH),
+                (ADDED_FOLDER_ID, ADDED_FOLDER_LABEL, ADDED_FOLDER_PATH),
+            ]
+        );
+        assert_eq!(
+            server_config
+                .devices
+                .iter()
+                .map(|d| (d.id.as_str(), d.name.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (LOCAL_DEVICE_ID, "local"),
+                (REMOTE_DEVICE_ID, REMOTE_DEVICE_NAME)
+            ]
+        );
+        assert_eq!(
+            server_config.folder(ADDED_FOLDER_ID).unwrap().label,
+            ADDED_FOLDER_LABEL
+        );
+        assert_eq!(
+            server_config
+                .device(short_device_id(REMOTE_DEVICE_ID))
+                .unwrap()
+                .name,
+            REMOTE_DEVICE_NAME
+        );
+        assert!(server_config.device("UNKNOWN").is_none());
+    }
+
+    /// The server configuration must be fetched once, and then served from the cache
+    #[test]
+    fn cache_server_config() {
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
+        let client = connect(server.url());
+
+        client.server_config().unwrap();
+        client.server_config().unwrap();
+
+        assert_eq!(server.config_requests(), 1);
+    }
+
+    /// The information of a file must be reported, with the device that last modified it by its
+    /// short id
+    #[test]
+    fn get_file_info() {
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
+        server.set_modified_by(FOLDER_ID, "sub/file.txt", short_device_id(REMOTE_DEVICE_ID));
+
+        let info = connect(server.url())
+            .file_info(FOLDER_ID, Path::new("sub/file.txt"))
+            .unwrap();
+
+        assert_eq!(info.size, 1234);
+        assert_eq!(info.modified, "2026-07-11T12:30:00.5+02:00");
+        assert_eq!(info.permissions, "0644");
+        assert_eq!(info.version, ["REMOTE1:1752229800", "LOCAL1:1752229000"]);
+        assert_eq!(info.modified_by, "REMOTE1");
+        assert_eq!(info.blocks_hash.as_deref(), Some("q83vEjRWeJA="));
+    }
+
     /// Events that occurred before we connected must not trigger hooks
     #[test]
     fn no_historical_event_replay_on_startup() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
-        server.push_event("ItemFinished", item_finished("old.txt", FOLDER_ID));
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
+        server.push_events(&file_synced("old.txt", FOLDER_ID));
 
         let events = stream_events(connect(server.url()), None);
 
         assert!(events.recv_timeout(NO_EVENT_DELAY).is_err());
 
         // Events occurring while connected are still delivered
-        server.push_event("ItemFinished", item_finished("new.txt", FOLDER_ID));
+        server.push_events(&file_synced("new.txt", FOLDER_ID));
         assert_eq!(recv_events(&events, 1), [file_down_sync_done("new.txt")]);
 
         // The event ids of a subscription are only comparable with those of the same
@@ -859,7 +1279,7 @@ mod tests {
     /// Events buffered by the server between two polls must all be delivered
     #[test]
     fn no_event_loss_on_burst() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
 
         let events = stream_events(connect(server.url()), None);
 
@@ -867,7 +1287,7 @@ mod tests {
         // events are not mistaken for events that occurred before we connected
         server.wait_event_requests(2);
         let items = ["1.txt", "2.txt", "3.txt"];
-        server.push_events(&items.map(|item| ("ItemFinished", item_finished(item, FOLDER_ID))));
+        server.push_events(&files_synced(&items));
 
         assert_eq!(
             recv_events(&events, items.len()),
@@ -878,12 +1298,12 @@ mod tests {
     /// Events that occurred while disconnected must be processed when the connection is back
     #[test]
     fn resume_event_stream_after_reconnection() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
         let items = ["1.txt", "2.txt", "3.txt"];
-        server.push_events(&items.map(|item| ("ItemFinished", item_finished(item, FOLDER_ID))));
+        server.push_events(&files_synced(&items));
 
-        // The previous connection consumed the first event before it was lost
-        let events = stream_events(connect(server.url()), Some(cursor(SERVER_START_TIME, 1)));
+        // The previous connection consumed the events of the first file before it was lost
+        let events = stream_events(connect(server.url()), Some(cursor(SERVER_START_TIME, 2)));
 
         assert_eq!(
             recv_events(&events, 2),
@@ -894,7 +1314,7 @@ mod tests {
     /// An item the sync did not update as a local file must not be reported as synced down
     #[test]
     fn ignore_item_finished_of_non_updated_file() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
 
         let events = stream_events(connect(server.url()), None);
 
@@ -922,8 +1342,16 @@ mod tests {
                 "ItemFinished",
                 item_finished_data("subdir", FOLDER_ID, None, "dir", "update"),
             ),
-            ("ItemFinished", item_finished("ok.txt", FOLDER_ID)),
+            (
+                "RemoteChangeDetected",
+                remote_change("deleted.txt", FOLDER_ID, "deleted"),
+            ),
+            (
+                "RemoteChangeDetected",
+                remote_change("chmod.txt", FOLDER_ID, "modified"),
+            ),
         ]);
+        server.push_events(&file_synced("ok.txt", FOLDER_ID));
 
         assert_eq!(recv_events(&events, 1), [file_down_sync_done("ok.txt")]);
         assert!(events.recv_timeout(NO_EVENT_DELAY).is_err());
@@ -932,7 +1360,7 @@ mod tests {
     /// A server closing the connection must be reported as gone, so the main loop reconnects
     #[test]
     fn server_gone_on_connection_close() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
         let relay = VanishingRelay::start(&server.url());
 
         let events = stream_events(connect(relay.url()), None);
@@ -949,7 +1377,7 @@ mod tests {
     /// A summary of a folder that still needs items must not be reported as synced down
     #[test]
     fn ignore_incomplete_folder_summary() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
 
         let events = stream_events(connect(server.url()), None);
 
@@ -970,7 +1398,7 @@ mod tests {
     /// A summary re-sent for the same state change must be reported only once
     #[test]
     fn ignore_duplicate_folder_summary() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
 
         let events = stream_events(connect(server.url()), None);
 
@@ -997,7 +1425,7 @@ mod tests {
     /// Only the modification of a conflict file must be reported as a local conflict
     #[test]
     fn file_conflict_on_conflict_file_modification() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
 
         let events = stream_events(connect(server.url()), None);
 
@@ -1032,10 +1460,32 @@ mod tests {
         assert!(events.recv_timeout(NO_EVENT_DELAY).is_err());
     }
 
+    /// Events must come with the server event they were built from
+    #[test]
+    fn report_server_event() {
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
+
+        let events = stream_events(connect(server.url()), None);
+
+        server.wait_event_requests(2);
+        server.push_events(&file_synced("a.txt", FOLDER_ID));
+
+        let (event, source) = events.recv_timeout(EVENT_DELAY).unwrap().unwrap();
+        assert_eq!(event, file_down_sync_done("a.txt"));
+        assert_eq!(source.id, 1);
+        assert_eq!(source.global_id, 1);
+        assert_eq!(source.event_type, syncthing_rest::EventType::ItemFinished);
+        assert_eq!(source.time, "2026-01-01T00:00:00Z");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(source.data.get()).unwrap(),
+            item_finished("a.txt", FOLDER_ID)
+        );
+    }
+
     /// An event of a folder absent from the server config must be skipped instead of crashing
     #[test]
     fn ignore_event_of_unknown_folder() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
 
         let events = stream_events(connect(server.url()), None);
 
@@ -1045,6 +1495,10 @@ mod tests {
                 "ItemFinished",
                 item_finished("unknown.txt", UNKNOWN_FOLDER_ID),
             ),
+            (
+                "RemoteChangeDetected",
+                remote_change("unknown.txt", UNKNOWN_FOLDER_ID, "added"),
+            ),
             (
                 "FolderSummary",
                 folder_summary(UNKNOWN_FOLDER_ID, 0, "2026-01-01T00:00:01Z"),
@@ -1058,8 +1512,8 @@ mod tests {
                     "modified",
                 ),
             ),
-            ("ItemFinished", item_finished("kept.txt", FOLDER_ID)),
         ]);
+        server.push_events(&file_synced("kept.txt", FOLDER_ID));
 
         assert_eq!(recv_events(&events, 1), [file_down_sync_done("kept.txt")]);
         // A single batch of events must not cost more than one configuration request
@@ -1069,21 +1523,22 @@ mod tests {
     /// An event of a folder configured since the folder map was built must still be reported
     #[test]
     fn refresh_folder_map_on_unknown_folder() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
 
         let events = stream_events(connect(server.url()), None);
 
         server.wait_event_requests(2);
         // The server applies a configuration change several seconds before it reports it with
         // a ConfigSaved event, so its folders can be ahead of the folder map
-        server.add_folder(ADDED_FOLDER_ID, ADDED_FOLDER_PATH);
-        server.push_event("ItemFinished", item_finished("new.txt", ADDED_FOLDER_ID));
+        server.add_folder(ADDED_FOLDER_ID, ADDED_FOLDER_LABEL, ADDED_FOLDER_PATH);
+        server.push_events(&file_synced("new.txt", ADDED_FOLDER_ID));
 
         assert_eq!(
             recv_events(&events, 1),
             [Event::FileDownSyncDone {
                 path: PathBuf::from("new.txt"),
                 folder: PathBuf::from(ADDED_FOLDER_PATH),
+                modified_by: short_device_id(REMOTE_DEVICE_ID).to_owned(),
             }]
         );
     }
@@ -1091,7 +1546,7 @@ mod tests {
     /// A server config change must interrupt the stream, so the folder map is rebuilt
     #[test]
     fn server_config_changed_on_config_saved() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
 
         let events = stream_events(connect(server.url()), None);
 
@@ -1102,29 +1557,74 @@ mod tests {
         assert!(err.downcast_ref::<ServerConfigChanged>().is_some());
     }
 
-    /// The cursor must be unset until primed, then track the last consumed event
+    /// The cursor must be unset until primed, then track the last consumed event, and the files
+    /// synced before it whose device is not reported yet
     #[test]
     fn cursor_tracks_stream_position() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
         server.push_event("ItemFinished", item_finished("1.txt", FOLDER_ID));
+        server.push_events(&file_synced("2.txt", FOLDER_ID));
 
         let client = connect(server.url());
         assert!(client.iter_events(None).cursor().is_none());
 
-        let mut events = client.iter_events(Some(&cursor(SERVER_START_TIME, 0)));
+        let mut events = client.iter_events(Some(cursor(SERVER_START_TIME, 0)));
+        assert_eq!(
+            events.next().unwrap().unwrap().0,
+            file_down_sync_done("2.txt")
+        );
+        let position = events.cursor().unwrap();
+        assert_eq!(position.server_start_time, SERVER_START_TIME);
+        assert_eq!(position.last_id, 3);
         assert_eq!(
-            events.next().unwrap().unwrap(),
-            file_down_sync_done("1.txt")
+            position.synced_files.keys().collect::<Vec<_>>(),
+            [&(FOLDER_ID.to_owned(), "1.txt".to_owned())]
+        );
+
+        // The device of the file synced before the connection was lost is still waited for
+        server.push_event(
+            "RemoteChangeDetected",
+            remote_change("1.txt", FOLDER_ID, "modified"),
         );
-        assert_eq!(events.cursor(), Some(cursor(SERVER_START_TIME, 1)));
+        let (event, source) = client.iter_events(Some(position)).next().unwrap().unwrap();
+        assert_eq!(event, file_down_sync_done("1.txt"));
+        assert_eq!(source.id, 1);
+    }
+
+    /// A synced file must only be reported once the server reports the device that changed it,
+    /// and with the event of the sync
+    #[test]
+    fn wait_for_device_of_synced_file() {
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
+
+        let events = stream_events(connect(server.url()), None);
+
+        server.wait_event_requests(2);
+        server.push_event("ItemFinished", item_finished("a.txt", FOLDER_ID));
+        assert!(events.recv_timeout(NO_EVENT_DELAY).is_err());
+
+        server.push_event(
+            "RemoteChangeDetected",
+            remote_change("a.txt", FOLDER_ID, "added"),
+        );
+        let (event, source) = events.recv_timeout(EVENT_DELAY).unwrap().unwrap();
+        assert_eq!(event, file_down_sync_done("a.txt"));
+        assert_eq!(source.event_type, syncthing_rest::EventType::ItemFinished);
+
+        // A change the sync did not download is not reported
+        server.push_event(
+            "RemoteChangeDetected",
+            remote_change("a.txt", FOLDER_ID, "modified"),
+        );
+        assert!(events.recv_timeout(NO_EVENT_DELAY).is_err());
     }
 
     /// A server that restarted numbers its events from scratch, its whole buffer must be processed
     #[test]
     fn restart_event_stream_after_server_restart() {
-        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_PATH)]);
+        let server = TestSyncthingServer::start(&[(FOLDER_ID, FOLDER_LABEL, FOLDER_PATH)]);
         let items = ["1.txt", "2.txt"];
-        server.push_events(&items.map(|item| ("ItemFinished", item_finished(item, FOLDER_ID))));
+        server.push_events(&files_synced(&items));
 
         // The previous connection was to another server instance, whose ids are unrelated to
         // those of this one, even when they are within the range of its event buffer
@@ -1138,4 +1638,24 @@ mod tests {
             items.map(file_down_sync_done)
         );
     }
+
+    /// String values of event payloads must be found by JSON pointer
+    #[test]
+    fn get_server_event_data() {
+        let event = ServerEvent {
+            id: 1,
+            global_id: 1,
+            event_type: syncthing_rest::EventType::FolderSummary,
+            time: String::new(),
+            data: serde_json::value::to_raw_value(&json!({
+                "folder": "fid1",
+                "summary": {"state": "idle", "needTotalItems": 0},
+            }))
+            .unwrap(),
+        };
+
+        assert_eq!(event.data_str("/summary/state").as_deref(), Some("idle"));
+        assert_eq!(event.data_str("/summary/needTotalItems"), None);
+        assert_eq!(event.data_str("/error"), None);
+    }
 }
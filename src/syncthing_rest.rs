@@ -355,8 +355,10 @@ pub(crate) enum EventType {
 pub(crate) struct Event {
     pub id: u64,
     pub global_id: u64,
+    pub kind: EventType,
     pub time: String,
     pub data: EventData,
+    pub raw_data: Box<serde_json::value::RawValue>,
 }
 
 impl TryFrom<RawEvent> for Event {
@@ -369,12 +371,13 @@ impl TryFrom<RawEvent> for Event {
             global_id,
             event_type,
             time,
-            data,
+            data: raw_data,
         } = raw_event;
-        let data = data.get();
+        let data = raw_data.get();
         Ok(Event {
             id,
             global_id,
+            kind: event_type,
             time,
             data: match event_type {
                 EventType::ConfigSaved => ConfigSaved(serde_json::from_str(data)?),
@@ -409,6 +412,7 @@ impl TryFrom<RawEvent> for Event {
                 EventType::StartupComplete => StartupComplete,
                 EventType::StateChanged => StateChanged(serde_json::from_str(data)?),
             },
+            raw_data,
         })
     }
 }
@@ -420,12 +424,46 @@ impl TryFrom<RawEvent> for Event {
 #[derive(serde::Deserialize)]
 pub(crate) struct SystemConfig {
     pub folders: Vec<SystemConfigFolder>,
+    pub devices: Vec<SystemConfigDevice>,
 }
 
 #[derive(serde::Deserialize)]
 pub(crate) struct SystemConfigFolder {
     pub path: String,
     pub id: String,
+    pub label: String,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct SystemConfigDevice {
+    #[serde(rename = "deviceID")]
+    pub device_id: String,
+    pub name: String,
+}
+
+//
+// /rest/db/file response
+//
+
+#[derive(serde::Deserialize)]
+pub(crate) struct DbFile {
+    pub global: DbFileInfo,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct DbFileInfo {
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub permissions: String,
+    #[serde(default)]
+    pub version: Vec<String>,
+    #[serde(rename = "modifiedBy")]
+    pub modified_by: String,
+    #[serde(default, rename = "blocksHash")]
+    pub blocks_hash: Option<String>,
 }
 
 //
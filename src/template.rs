@@ -0,0 +1,307 @@
+//! Templates with placeholders substituted by the values of a hook run
+
+use std::{
+    ffi::{OsStr, OsString},
+    fmt, mem,
+    os::unix::ffi::OsStrExt as _,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::hook;
+
+/// Error when parsing a template
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum Error {
+    /// Placeholder with an unknown name
+    #[error("Unknown placeholder {{{0}}}, use '{{{{{0}}}}}' for a literal one")]
+    UnknownPlaceholder(String),
+}
+
+/// Value substituted in a template
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Placeholder {
+    /// Path of the event file, relative to the folder
+    Path,
+    /// Absolute path of the event file
+    AbsPath,
+    /// Local path of the folder
+    Folder,
+    /// Syncthing label of the folder
+    FolderLabel,
+    /// Event kind
+    Event,
+    /// File name of the event file
+    Basename,
+}
+
+impl Placeholder {
+    /// All placeholders
+    const ALL: [Self; 6] = [
+        Self::Path,
+        Self::AbsPath,
+        Self::Folder,
+        Self::FolderLabel,
+        Self::Event,
+        Self::Basename,
+    ];
+
+    /// Name of the placeholder in templates
+    fn name(self) -> &'static str {
+        match self {
+            Self::Path => "path",
+            Self::AbsPath => "abs_path",
+            Self::Folder => "folder",
+            Self::FolderLabel => "folder_label",
+            Self::Event => "event",
+            Self::Basename => "basename",
+        }
+    }
+
+    /// Value of the placeholder for an event context, empty if the event has no file
+    fn value(self, ctx: &hook::Context) -> OsString {
+        let value = match self {
+            Self::Path => ctx.path.clone().map(Into::into),
+            Self::AbsPath => ctx.abs_path().map(Into::into),
+            Self::Folder => Some(ctx.folder.clone().into()),
+            Self::FolderLabel => Some(ctx.folder_label.clone().into()),
+            Self::Event => Some(ctx.event.name().into()),
+            Self::Basename => ctx
+                .path
+                .as_deref()
+                .and_then(Path::file_name)
+                .map(OsStr::to_os_string),
+        };
+        value.unwrap_or_default()
+    }
+
+    /// Placeholder with a given name
+    fn from_name(name: &str) -> Result<Self, Error> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.name() == name)
+            .ok_or_else(|| Error::UnknownPlaceholder(name.to_owned()))
+    }
+}
+
+/// Whether some text between braces is meant as a placeholder name, rather than eg. shell or awk
+/// code
+fn is_placeholder_name(s: &str) -> bool {
+    s.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Part of a template
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Segment {
+    /// Text copied as is
+    Literal(String),
+    /// Value substituted for each run
+    Placeholder(Placeholder),
+}
+
+/// Text with `{name}` placeholders, and `{{name}}` for a literal `{name}`
+///
+/// Braces around anything else than a name, or following a `$`, are kept as is, so that commands
+/// like `awk '{print $1}'` or `echo ${HOME}` need no escaping.
+#[derive(Clone, Eq, PartialEq)]
+pub(crate) struct Template {
+    /// Text as written in the configuration
+    source: String,
+    /// Parsed text
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Substitute the placeholders with their values for an event context
+    pub(crate) fn render(&self, ctx: &hook::Context) -> String {
+        self.render_os(ctx).to_string_lossy().into_owned()
+    }
+
+    /// Substitute the placeholders with their values for an event context, keeping paths that
+    /// are not valid Unicode as is, ie. for command arguments
+    pub(crate) fn render_os(&self, ctx: &hook::Context) -> OsString {
+        self.render_with(|p| p.value(ctx))
+    }
+
+    /// Substitute the placeholders with their values quoted for a shell, for an event context
+    pub(crate) fn render_quoted(&self, ctx: &hook::Context) -> OsString {
+        self.render_with(|p| {
+            let value = p.value(ctx);
+            // Values can not contain NUL bytes, the only unquotable ones
+            let quoted = shlex::bytes::try_quote(value.as_bytes()).unwrap_or_default();
+            OsStr::from_bytes(&quoted).to_os_string()
+        })
+    }
+
+    /// Substitute the placeholders with the values returned by `value`
+    fn render_with(&self, value: impl Fn(Placeholder) -> OsString) -> OsString {
+        self.segments
+            .iter()
+            .fold(OsString::new(), |mut rendered, segment| {
+                match segment {
+                    Segment::Literal(l) => rendered.push(l),
+                    Segment::Placeholder(p) => rendered.push(value(*p)),
+                }
+                rendered
+            })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+// Shown as in the configuration, to keep logs of commands readable
+impl fmt::Debug for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_string(), f)
+    }
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = s;
+        while let Some((before, after)) = rest.split_once('{') {
+            literal.push_str(before);
+            let escaped = after
+                .strip_prefix('{')
+                .and_then(|a| a.split_once("}}"))
+                .filter(|(name, _)| is_placeholder_name(name));
+            let named = after
+                .split_once('}')
+                .filter(|(name, _)| !literal.ends_with('$') && is_placeholder_name(name));
+            if let Some((name, tail)) = escaped {
+                literal.push('{');
+                literal.push_str(name);
+                literal.push('}');
+                rest = tail;
+            } else if let Some((name, tail)) = named {
+                let placeholder = Placeholder::from_name(name)?;
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(mem::take(&mut literal)));
+                }
+                segments.push(Segment::Placeholder(placeholder));
+                rest = tail;
+            } else {
+                literal.push('{');
+                rest = after;
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self {
+            source: s.to_owned(),
+            segments,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Context of an event for `docs/a.txt` in `/data/folder`
+    fn context() -> hook::Context {
+        hook::Context {
+            event: crate::config::FolderEvent::FileDownSyncDone,
+            path: Some(PathBuf::from("docs/a.txt")),
+            folder: PathBuf::from("/data/folder"),
+            folder_id: String::new(),
+            folder_label: "Folder".to_owned(),
+            source: None,
+            device_id: None,
+            device_name: None,
+            file: None,
+            subdir: PathBuf::new(),
+            captures: Vec::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Placeholders must be substituted, doubled names unescaped, and other braces kept
+    #[test]
+    fn render_templates() {
+        let ctx = context();
+        for (template, expected) in [
+            ("{path}", "docs/a.txt"),
+            ("{abs_path}", "/data/folder/docs/a.txt"),
+            ("{folder}", "/data/folder"),
+            ("--name={basename}", "--name=a.txt"),
+            ("{folder_label}/{event}", "Folder/file_down_sync_done"),
+            ("{{path}} {path}", "{path} docs/a.txt"),
+            ("{{{path}}}", "{{path}}"),
+            ("{{file}} ${{HOME}}", "{file} ${HOME}"),
+            ("${path}", "${path}"),
+            ("no placeholder", "no placeholder"),
+            ("awk '{print $1}' {path}", "awk '{print $1}' docs/a.txt"),
+            ("${HOME} {} {{}} }{unknown", "${HOME} {} {{}} }{unknown"),
+            (
+                "find {folder} -exec rm {} ;",
+                "find /data/folder -exec rm {} ;",
+            ),
+        ] {
+            let template: Template = template.parse().unwrap();
+            assert_eq!(template.render(&ctx), expected);
+        }
+
+        let folder_event = hook::Context { path: None, ..ctx };
+        let template: Template = "{folder}:{abs_path}".parse().unwrap();
+        assert_eq!(template.render(&folder_event), "/data/folder:");
+    }
+
+    /// Placeholders must be quoted when rendering for a shell
+    #[test]
+    fn render_quoted_templates() {
+        let ctx = hook::Context {
+            path: Some(PathBuf::from("docs/it's $HOME.txt")),
+            ..context()
+        };
+        let template: Template = "cat {path} > {basename}.bak".parse().unwrap();
+        assert_eq!(
+            template.render_quoted(&ctx),
+            r#"cat "docs/it's "'$HOME.txt' > "it's "'$HOME.txt'.bak"#
+        );
+    }
+
+    /// Templates must be shown as they are written
+    #[test]
+    fn display_templates() {
+        for s in [
+            "{path}",
+            "{{event}} {{}} {{file}}",
+            "{{{event}}}",
+            "awk '{print $1}' ${HOME}",
+            "--label={folder_label}",
+            "plain",
+        ] {
+            let template: Template = s.parse().unwrap();
+            assert_eq!(template.to_string(), s);
+        }
+    }
+
+    /// Templates with unknown placeholders must be rejected
+    #[test]
+    fn reject_invalid_templates() {
+        for s in [
+            "{unknown}",
+            "{file}",
+            "cp {path} /backup/{base_name}",
+            "{_}",
+        ] {
+            assert!(s.parse::<Template>().is_err(), "{s:?}");
+        }
+    }
+}